@@ -1,11 +1,19 @@
 use crate::helpers;
+use crate::parse;
+use crate::structs::Watch;
+use crate::task;
 
 use colored::Colorize;
+use human_bytes::human_bytes;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use inquire::Text;
-use macros_rs::string;
+use macros_rs::{crashln, string, ternary};
 use notify::RecursiveMode;
 use notify_debouncer_mini::new_debouncer;
-use std::{fs::File, io::Write, path::Path, time::Duration};
+use std::process::{Child, Command, Stdio};
+use std::sync::{atomic::AtomicU32, atomic::Ordering, mpsc::RecvTimeoutError, Arc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{collections::BTreeSet, fs::File, io::IsTerminal, io::Write};
 
 fn create_error(name: &str) {
     println!("An error happened when asking for {name}, try again later.");
@@ -13,16 +21,148 @@ fn create_error(name: &str) {
     std::process::exit(1);
 }
 
-pub fn watch(path: &Path) {
+fn spawn_task(task: &[String], path: &str, jobs: usize) -> Child {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => crashln!("Unable to find the maid executable.\n{err}"),
+    };
+
+    let mut cmd = Command::new(exe);
+    cmd.args(task).args(["--path", path, "--jobs", &jobs.to_string()]).stdin(Stdio::null());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) => crashln!("Cannot start task '{}'.\n{err}", task[0]),
+    }
+}
+
+fn kill_task(pid: u32) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGTERM);
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = Command::new("taskkill").args(["/T", "/F", "/PID", &pid.to_string()]).stdout(Stdio::null()).stderr(Stdio::null()).status();
+    }
+}
+
+fn stop_task(child: &mut Child) {
+    if let Ok(None) = child.try_wait() {
+        kill_task(child.id());
+        child.wait().ok();
+    }
+}
+
+fn watch_banner(message: String) {
+    if std::io::stdout().is_terminal() {
+        print!("\x1B[2J\x1B[1;1H");
+    }
+    println!("{} {}", "[watch]".bright_magenta(), message);
+}
+
+pub fn watch(path: &String, task: &[String], jobs: usize) {
+    let name = match task.first().filter(|name| !name.trim().is_empty()) {
+        Some(name) => name.trim(),
+        None => crashln!("Specify a task to watch, e.g. 'maid butler watch build'."),
+    };
+
+    let values = helpers::maidfile::merge(path);
+    let project_root = parse::file::find_maidfile_root(path);
+    let root = project_root.canonicalize().unwrap_or(project_root);
+
+    let config = match values.tasks.get(name) {
+        Some(task) => task.watch.clone().unwrap_or(Watch { include: None, exclude: None }),
+        None => crashln!("Maid could not find the task '{name}'. Does it exist?"),
+    };
+
+    let include = helpers::file::glob_set(&config.include.unwrap_or_default());
+    let exclude = helpers::file::glob_set(&[config.exclude.unwrap_or_default(), vec![string!(".maid/**"), string!(".git/**")]].concat());
+
+    let mut builder = GitignoreBuilder::new(&root);
+    if let Some(err) = builder.add(root.join(".gitignore")) {
+        log::debug!("{err}");
+    }
+
+    let gitignore = builder.build().unwrap_or_else(|_| Gitignore::empty());
     let (tx, rx) = std::sync::mpsc::channel();
-    let mut debouncer = new_debouncer(Duration::from_secs(1), tx).unwrap();
+    let mut debouncer = match new_debouncer(Duration::from_millis(500), tx) {
+        Ok(debouncer) => debouncer,
+        Err(err) => crashln!("Unable to start file watcher.\n{err}"),
+    };
+
+    if let Err(err) = debouncer.watcher().watch(&root, RecursiveMode::Recursive) {
+        crashln!("Unable to watch {:?}.\n{err}", root);
+    }
+
+    let running = Arc::new(AtomicU32::new(0));
+    let handler = Arc::clone(&running);
 
-    debouncer.watcher().watch(path, RecursiveMode::Recursive).unwrap();
-    for events in rx {
-        if let Ok(event) = events {
-            println!("{:?}", event);
+    if let Err(err) = ctrlc::set_handler(move || {
+        match handler.load(Ordering::SeqCst) {
+            0 => {}
+            pid => kill_task(pid),
+        };
+        std::process::exit(130);
+    }) {
+        log::warn!("{err}");
+    }
+
+    watch_banner(format!("watching {} for changes, running '{name}'", root.display().to_string().bright_cyan()));
+    let mut child = spawn_task(task, path, jobs);
+    let mut finished = false;
+    running.store(child.id(), Ordering::SeqCst);
+
+    loop {
+        match rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(Ok(events)) => {
+                let changed: Vec<String> = events
+                    .iter()
+                    .filter(|event| !gitignore.matched_path_or_any_parents(&event.path, event.path.is_dir()).is_ignore())
+                    .filter_map(|event| event.path.strip_prefix(&root).ok())
+                    .filter(|relative| !exclude.is_match(relative) && (include.is_empty() || include.is_match(relative)))
+                    .map(|relative| relative.to_string_lossy().to_string())
+                    .collect();
+
+                if changed.is_empty() {
+                    continue;
+                }
+
+                log::debug!("changed files: {:?}", changed);
+                stop_task(&mut child);
+
+                let more = ternary!(changed.len() > 1, format!(" and {} more", changed.len() - 1), string!());
+                watch_banner(format!("{}{more} changed, restarting '{name}'", changed[0].bright_cyan()));
+
+                child = spawn_task(task, path, jobs);
+                finished = false;
+                running.store(child.id(), Ordering::SeqCst);
+            }
+            Ok(Err(err)) => log::warn!("{err}"),
+            Err(RecvTimeoutError::Timeout) => {
+                if let Ok(Some(status)) = child.try_wait() {
+                    if !finished {
+                        let code = status.code().unwrap_or(1);
+                        let message = ternary!(code == 0, "task finished".bright_green(), format!("task exited with status code {code}").bright_red());
+
+                        println!("\n{} {}, waiting for changes", "[watch]".bright_magenta(), message);
+                        finished = true;
+                        running.store(0, Ordering::SeqCst);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
         }
     }
+
+    stop_task(&mut child);
 }
 
 pub fn update() { println!("check and retrive updates") }
@@ -70,3 +210,76 @@ pub fn clean() {
         Err(_) => println!("{}", "maid cache does not exist, cannot remove".yellow()),
     };
 }
+
+pub fn ago(timestamp: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+    let secs = now.saturating_sub(timestamp);
+
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+pub fn cache_ls(task: &Option<String>) {
+    let mut entries = task::cache::entries();
+
+    entries.retain(|entry| task.as_ref().map_or(true, |task| &entry.task == task));
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.accessed));
+
+    if entries.is_empty() {
+        return println!("{}", "maid cache is empty".yellow());
+    }
+
+    for entry in entries {
+        println!(
+            "{} {} {}{}{} {}",
+            entry.task.bright_yellow(),
+            entry.key[..12].white(),
+            format!("{} {}", entry.files.len(), ternary!(entry.files.len() == 1, "file", "files")).bright_cyan(),
+            helpers::string::seperator(),
+            human_bytes(entry.size as f64).bright_cyan(),
+            format!("(used {})", ago(entry.accessed)).white()
+        );
+    }
+}
+
+pub fn cache_stats(path: &String) {
+    let values = helpers::maidfile::merge(path);
+    let entries = task::cache::entries();
+    let blobs = task::cache::blobs();
+
+    let stored: u64 = blobs.values().sum();
+    let logical: u64 = entries.iter().map(|entry| entry.size).sum();
+    let tasks: BTreeSet<&String> = entries.iter().map(|entry| &entry.task).collect();
+
+    let limit = match task::cache::limit(&values) {
+        Some(limit) => human_bytes(limit as f64),
+        None => string!("none"),
+    };
+
+    println!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        "Cache Stats".green().bold(),
+        format!(" {}: {}", "- Entries".white(), format!("{} ({} tasks)", entries.len(), tasks.len()).bright_yellow()),
+        format!(" {}: {}", "- Blobs".white(), blobs.len().to_string().bright_yellow()),
+        format!(" {}: {}", "- Stored".white(), human_bytes(stored as f64).bright_yellow()),
+        format!(" {}: {}", "- Deduplicated".white(), human_bytes(logical.saturating_sub(stored) as f64).bright_yellow()),
+        format!(" {}: {}", "- Limit".white(), limit.bright_yellow())
+    );
+}
+
+pub fn cache_prune(path: &String, limit: &Option<String>) {
+    let limit = match limit {
+        Some(limit) => match task::cache::parse_size(limit) {
+            Some(limit) => Some(limit),
+            None => crashln!("Invalid cache limit '{limit}', expected a size like \"500MB\"."),
+        },
+        None => task::cache::limit(&helpers::maidfile::merge(path)),
+    };
+
+    let (removed, freed) = task::cache::prune(limit);
+    println!("{}", format!("removed {removed} cache {}, freed {}", ternary!(removed == 1, "entry", "entries"), human_bytes(freed as f64)).green());
+}
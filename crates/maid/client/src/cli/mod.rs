@@ -1,15 +1,13 @@
 use crate::helpers;
 use crate::parse;
 use crate::server;
-use crate::structs::{Cache, CacheConfig, Task};
+use crate::structs::{Cache, Outcome, Task};
 use crate::task;
 
 use colored::Colorize;
-use fs_extra::dir::get_size;
-use global_placeholders::global;
-use human_bytes::human_bytes;
-use macros_rs::{crashln, fmtstr, string, ternary};
-use std::{env, path::Path, time::Instant};
+use macros_rs::{crashln, string, ternary};
+use maid_protocol::table;
+use std::{collections::BTreeMap, env, time::Instant};
 
 pub fn get_version(short: bool) -> String {
     return match short {
@@ -47,14 +45,14 @@ pub fn info(path: &String) {
     );
 }
 
-pub fn exec(task: &str, args: &Vec<String>, path: &String, silent: bool, is_dep: bool, is_remote: bool, log_level: Option<log::Level>) {
+pub fn exec(task: &str, args: &Vec<String>, path: &String, silent: bool, is_dep: bool, is_remote: bool, log_level: Option<log::Level>, jobs: usize) -> Outcome {
     log::info!("Starting maid {}", env!("CARGO_PKG_VERSION"));
 
     if task.is_empty() {
         if is_remote {
-            tasks::List::remote(path, silent, log_level);
+            tasks::List::remote(path, silent, log_level, jobs)
         } else {
-            tasks::List::all(path, silent, log_level);
+            tasks::List::all(path, silent, log_level, jobs)
         }
     } else {
         let values = helpers::maidfile::merge(path);
@@ -78,39 +76,79 @@ pub fn exec(task: &str, args: &Vec<String>, path: &String, silent: bool, is_dep:
             None => {}
         }
 
-        if !is_remote {
-            match &values.tasks[task].depends {
-                Some(deps) => {
-                    let start = Instant::now();
-                    let ticks = vec!["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-                    let template = fmtstr!("{{prefix:.white}} {{spinner:.yellow}}{{msg}} {}", "({elapsed})".bright_cyan());
-                    let pb = task::progress::init(ticks, template, 80);
-
-                    for (index, item) in deps.iter().enumerate() {
-                        pb.set_prefix(format!("[{}/{}]", index + 1, deps.len()));
-                        pb.set_message(fmtstr!("{} {item}", "running dependency".bright_yellow()));
-                        exec(&item, args, path, true, true, is_remote, log_level);
-                    }
+        let declared = values.tasks[task].params.clone().unwrap_or_default();
+        let (positional, provided) = params::parse(args, &declared);
+
+        if provided.contains_key("help") && !is_dep {
+            params::help(task, &values.tasks[task]);
+
+            return Outcome::ok(task);
+        }
 
-                    if !is_dep {
-                        pb.suspend(|| {
-                            println!(
-                                "{} {} in {} {}\n",
-                                helpers::string::check_icon(),
-                                format!("finished {} {}", deps.len(), ternary!(deps.len() > 1, "dependencies", "dependency")).bright_green(),
-                                format!("{:.2?}", start.elapsed()).yellow(),
-                                format!("[{}]", deps.join(", ")).white()
-                            )
-                        });
+        if !is_dep {
+            let accepted: Vec<String> = match is_remote {
+                true => declared.keys().cloned().collect(),
+                false => task::graph::Graph::build(&values, task)
+                    .nodes
+                    .keys()
+                    .filter_map(|name| values.tasks[name].params.as_ref())
+                    .flat_map(|params| params.keys().cloned())
+                    .collect(),
+            };
+
+            params::check(task, &provided, &accepted);
+        }
+
+        let vars: BTreeMap<String, String> = params::resolve(task, &declared, &provided, is_dep).into_iter().map(|(key, value)| (format!("param.{key}"), value)).collect();
+
+        if let Some(deps) = values.tasks[task].depends.as_ref().filter(|deps| !deps.is_empty() && !is_remote && !is_dep) {
+            let start = Instant::now();
+            let graph = task::graph::Graph::build(&values, task);
+            let order = graph.order();
+
+            log::debug!("Direct dependencies: {:?}", deps);
+            let finished = match task::scheduler::run(&graph, jobs, |item| exec(item, args, path, true, true, is_remote, log_level, jobs)) {
+                Ok(finished) => finished,
+                Err(failed) => {
+                    if let Some(output) = failed.output.as_ref().filter(|output| !output.trim().is_empty()) {
+                        println!("{} {}\n{}", helpers::string::arrow_icon(), format!("output of '{}'", failed.name).white(), output.trim_end());
                     }
+
+                    let location = match &failed.line {
+                        Some((line, script)) => format!(" at line {line} '{script}'"),
+                        None => string!(),
+                    };
+
+                    println!(
+                        "\n{} {} {}",
+                        helpers::string::cross_icon(),
+                        format!("dependency '{}'{location} exited with status code", failed.name).bright_red(),
+                        format!("{}", failed.code).red()
+                    );
+
+                    std::process::exit(failed.code);
                 }
-                None => {}
             };
+
+            let skipped: BTreeMap<&String, &str> = finished.iter().filter_map(|outcome| outcome.skipped.map(|reason| (&outcome.name, reason))).collect();
+            let names: Vec<String> = order.iter().map(|name| ternary!(skipped.contains_key(name), format!("{name} ({})", skipped[name]), name.clone())).collect();
+
+            let mut reasons: BTreeMap<&str, usize> = BTreeMap::new();
+            skipped.values().for_each(|reason| *reasons.entry(reason).or_default() += 1);
+            let summary: String = reasons.iter().map(|(reason, count)| format!(", {count} {reason}")).collect();
+
+            println!(
+                "{} {} in {} {}\n",
+                helpers::string::check_icon(),
+                format!("finished {} {}{summary}", order.len(), ternary!(order.len() > 1, "dependencies", "dependency")).bright_green(),
+                format!("{:.2?}", start.elapsed()).yellow(),
+                format!("[{}]", names.join(", ")).white()
+            );
         }
 
         let cache = match &values.tasks[task].cache {
             Some(cache) => cache.clone(),
-            None => Cache { path: string!(""), target: vec![] },
+            None => Cache { path: None, inputs: None, target: vec![] },
         };
 
         let task_path = match &values.tasks[task].path {
@@ -119,75 +157,37 @@ pub fn exec(task: &str, args: &Vec<String>, path: &String, silent: bool, is_dep:
         }
         .to_string();
 
-        if !cache.path.trim().is_empty() && !cache.target.is_empty() && !is_remote {
-            if !helpers::Exists::folder(global!("maid.cache_dir", task)).unwrap() {
-                std::fs::create_dir_all(global!("maid.cache_dir", task)).unwrap();
-                log::debug!("created maid cache dir");
+        let cache_hash = match task::cache::enabled(&cache) && !is_remote {
+            true => {
+                let table = table::create(values.clone(), &positional, project_root.clone(), Some(&string!(task)), &vars, true);
+                Some(task::cache::create_hash(task, &values, &table, &task::cache::inputs(&cache), &project_root))
             }
+            false => None,
+        };
 
-            let hash = task::cache::create_hash(&cache.path);
-            let config_path = format!(".maid/cache/{task}/{}.toml", task);
-
-            if !helpers::Exists::file(config_path.clone()).unwrap() {
-                match std::fs::write(
-                    config_path.clone(),
-                    toml::to_string(&CacheConfig {
-                        target: cache.target.clone(),
-                        hash: string!(""),
-                    })
-                    .unwrap(),
-                ) {
-                    Ok(_) => log::debug!("created {task} cache config"),
-                    Err(err) => crashln!("error {err} creating cache config"),
-                };
-            }
-
-            let contents = match std::fs::read_to_string(config_path.clone()) {
-                Ok(content) => content,
-                Err(err) => crashln!("Cannot read cache config: {err}"),
-            };
-
-            let json = match toml::from_str::<CacheConfig>(&contents) {
-                Ok(contents) => contents,
-                Err(err) => crashln!("Cannot read cache config: {err}"),
+        if let Some(hash) = &cache_hash {
+            let entry = match task::cache::lookup(task, hash) {
+                Some(entry) => Some(entry),
+                None if server::cache::enabled(&values) => server::cache::pull(&values, task, hash, &cache, silent),
+                None => None,
             };
 
-            if json.hash == hash && !is_dep {
-                println!("{}", "skipping task due to cached files".bright_magenta());
-
-                for target in cache.target.clone() {
-                    let cache_file = format!(".maid/cache/{task}/target/{}", Path::new(&target.clone()).file_name().unwrap().to_str().unwrap());
+            if let Some(entry) = entry {
+                task::cache::restore(&entry, &cache, &project_root, silent);
 
-                    println!(
-                        "{} ({})",
-                        format!("copied target '{}' from cache", target.clone()).magenta(),
-                        format!("{}", human_bytes(get_size(cache_file.clone()).unwrap() as f64).white())
-                    );
+                return Outcome::skipped(task, "cached");
+            }
+        }
 
-                    match std::fs::copy(Path::new(&cache_file), target.clone()) {
-                        Ok(_) => log::debug!("copied target file {}", target),
-                        Err(err) => {
-                            log::warn!("{err}");
-                            crashln!("Cannot copy target file.");
-                        }
-                    };
-                }
+        let check_sources = task::sources::enabled(&values, task) && !is_remote;
 
-                std::process::exit(0);
-            } else {
-                match std::fs::write(
-                    config_path.clone(),
-                    toml::to_string(&CacheConfig {
-                        target: cache.target,
-                        hash: hash.clone(),
-                    })
-                    .unwrap(),
-                ) {
-                    Ok(_) => log::debug!("added hash for {task} -> {hash}"),
-                    Err(err) => crashln!("error {err} creating cache config"),
-                };
+        if check_sources && task::sources::up_to_date(&values, task, &project_root) {
+            if !silent {
+                println!("{} {}", helpers::string::check_icon(), format!("{task} is up to date").bright_green());
             }
-        };
+
+            return Outcome::skipped(task, "up to date");
+        }
 
         log::debug!("Is remote?: {is_remote}");
         log::debug!("Project dir: {:?}", project_root);
@@ -204,33 +204,65 @@ pub fn exec(task: &str, args: &Vec<String>, path: &String, silent: bool, is_dep:
         }
 
         if is_remote {
-            server::cli::remote(Task {
+            let code = server::cli::remote(Task {
                 maidfile: values.clone(),
                 name: string!(task),
                 project: project_root,
                 remote: values.tasks[task].remote.clone(),
                 script: values.tasks[task].script.clone(),
                 path: task_path.clone(),
-                args: args.clone(),
+                args: positional.clone(),
+                vars: vars.clone(),
                 silent,
                 is_dep,
             });
+
+            match code {
+                0 => Outcome::ok(task),
+                code => Outcome::failed(task, code, None),
+            }
         } else {
-            run::task(Task {
+            let local = Task {
                 maidfile: values.clone(),
                 name: string!(task),
-                project: project_root,
+                project: project_root.clone(),
                 remote: None,
                 script: values.tasks[task].script.clone(),
                 path: task_path.clone(),
-                args: args.clone(),
+                args: positional.clone(),
+                vars: vars.clone(),
                 silent,
                 is_dep,
-            });
+            };
+
+            let outcome = match &values.tasks[task].matrix {
+                Some(matrix) if !matrix.is_empty() => run::matrix(local, task::matrix::expand(matrix), values.tasks[task].parallel.unwrap_or(false), jobs),
+                _ => run::task(local),
+            };
+
+            if check_sources && outcome.code == 0 {
+                task::sources::record(&values, task, &project_root);
+            }
+
+            if let Some(hash) = cache_hash.filter(|_| outcome.code == 0) {
+                let entry = task::cache::save(task, &hash, &cache, &project_root, silent);
+
+                if server::cache::enabled(&values) {
+                    server::cache::push(&values, &entry, silent);
+                }
+            }
+
+            // dependencies save their entries from scheduler threads, only prune once all of them have joined
+            if let Some(limit) = task::cache::limit(&values).filter(|_| !is_dep) {
+                task::cache::prune(Some(limit));
+            }
+
+            return outcome;
         }
     }
 }
 
 pub mod butler;
+pub mod params;
 pub mod run;
 pub mod tasks;
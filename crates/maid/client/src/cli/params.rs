@@ -0,0 +1,321 @@
+use crate::helpers;
+use crate::structs::{Param, ParamKind, Tasks};
+use crate::task;
+
+use colored::Colorize;
+use inquire::{validator::Validation, Confirm, Select, Text};
+use macros_rs::{crashln, string, ternary, then};
+use std::{collections::BTreeMap, io::IsTerminal};
+use toml::Value;
+
+fn value_to_string(value: &Value) -> String {
+    match value.as_str() {
+        Some(value) => string!(value),
+        None => value.to_string(),
+    }
+}
+
+fn options(command: &clap::Command, known: &mut BTreeMap<String, bool>, subcommands: &mut Vec<String>) {
+    for arg in command.get_arguments() {
+        let takes_value = arg.get_action().takes_values();
+
+        if let Some(long) = arg.get_long() {
+            known.insert(format!("--{long}"), takes_value);
+        }
+        if let Some(short) = arg.get_short() {
+            known.insert(format!("-{short}"), takes_value);
+        }
+    }
+
+    for subcommand in command.get_subcommands() {
+        subcommands.push(string!(subcommand.get_name()));
+        options(subcommand, known, subcommands);
+    }
+}
+
+/// whether `--name` is a declared non-bool parameter of the task or its dependencies, its value may then start with a dash
+pub fn takes_value(path: &String, task: &str, name: &str) -> bool {
+    let values = helpers::maidfile::merge(path);
+    then!(!values.tasks.contains_key(task), return false);
+
+    task::graph::Graph::build(&values, task)
+        .nodes
+        .keys()
+        .filter_map(|node| values.tasks[node].params.as_ref()?.get(name))
+        .any(|param| param.kind.as_ref() != Some(&ParamKind::Bool))
+}
+
+/// moves `--name value` pairs following a task name out of argv, so clap does not reject them
+///
+/// a value starting with a dash, such as `--offset -5`, is only kept when `declared(path, task, name)` says the parameter takes one
+pub fn split(argv: Vec<String>, command: &mut clap::Command, declared: impl Fn(&String, &str, &str) -> bool) -> (Vec<String>, Vec<String>) {
+    let mut known: BTreeMap<String, bool> = BTreeMap::new();
+    let mut subcommands: Vec<String> = vec![];
+
+    command.build();
+    options(command, &mut known, &mut subcommands);
+
+    let path_arg = command.get_arguments().find(|arg| arg.get_id() == "path");
+    let path_flags: Vec<String> = path_arg.map(|arg| [arg.get_long().map(|long| format!("--{long}")), arg.get_short().map(|short| format!("-{short}"))].into_iter().flatten().collect()).unwrap_or_default();
+    let mut path: String = path_arg.and_then(|arg| arg.get_default_values().first()).map(|value| value.to_string_lossy().to_string()).unwrap_or_default();
+
+    let mut cli: Vec<String> = vec![];
+    let mut params: Vec<String> = vec![];
+    let mut task: Option<String> = None;
+    let mut iter = argv.into_iter().peekable();
+
+    cli.extend(iter.next());
+
+    while let Some(arg) = iter.next() {
+        let name = arg.split('=').next().unwrap_or_default();
+        let is_help = arg == "--help" || arg == "-h";
+
+        if arg == "--" {
+            cli.push(arg);
+            cli.extend(iter.by_ref());
+        } else if let Some(task) = task.as_ref().filter(|_| is_help || (arg.starts_with("--") && !known.contains_key(name))) {
+            let takes_value = !arg.contains('=')
+                && !is_help
+                && iter.peek().is_some_and(|next| !next.starts_with('-') || (!next.starts_with("--") && declared(&path, task, &name[2..])));
+
+            params.push(arg);
+            if takes_value {
+                params.extend(iter.next());
+            }
+        } else if let Some(takes_value) = known.get(name) {
+            let takes_value = *takes_value && !arg.contains('=');
+
+            if path_flags.iter().any(|flag| flag == name) {
+                path = match arg.split_once('=') {
+                    Some((_, value)) => string!(value),
+                    None => iter.peek().cloned().unwrap_or_default(),
+                };
+            }
+
+            cli.push(arg);
+            if takes_value {
+                cli.extend(iter.next());
+            }
+        } else {
+            if task.is_none() && !arg.starts_with('-') && !subcommands.contains(&arg) {
+                task = Some(arg.clone());
+            }
+            cli.push(arg);
+        }
+    }
+
+    log::trace!("cli args: {:?}, task params: {:?}", cli, params);
+    (cli, params)
+}
+
+/// separates positional task arguments from `--name value` parameters
+pub fn parse(args: &Vec<String>, declared: &BTreeMap<String, Param>) -> (Vec<String>, BTreeMap<String, String>) {
+    let mut positional: Vec<String> = vec![];
+    let mut provided: BTreeMap<String, String> = BTreeMap::new();
+    let mut iter = args.iter().peekable();
+
+    while let Some(arg) = iter.next() {
+        if arg == "-h" || arg == "--help" {
+            provided.insert(string!("help"), string!("true"));
+            continue;
+        }
+
+        let flag = match arg.strip_prefix("--") {
+            Some(flag) if !flag.is_empty() => flag,
+            _ => {
+                positional.push(arg.clone());
+                continue;
+            }
+        };
+
+        let is_bool = declared.get(flag).and_then(|param| param.kind.as_ref()) == Some(&ParamKind::Bool);
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (string!(name), string!(value)),
+            None if is_bool && !iter.peek().is_some_and(|next| *next == "true" || *next == "false") => (string!(flag), string!("true")),
+            None => match iter.next_if(|next| !next.starts_with("--")) {
+                Some(value) => (string!(flag), value.clone()),
+                None => (string!(flag), string!("")),
+            },
+        };
+
+        provided.insert(name, value);
+    }
+
+    (positional, provided)
+}
+
+fn validate(name: &str, param: &Param, value: &str) -> Result<(), String> {
+    match param.kind.as_ref().unwrap_or(&ParamKind::String) {
+        ParamKind::Number if value.parse::<f64>().is_err() => return Err(format!("'{value}' is not a valid number for --{name}")),
+        ParamKind::Bool if value != "true" && value != "false" => return Err(format!("'{value}' is not a valid bool for --{name}")),
+        _ => {}
+    }
+
+    if let Some(values) = &param.values {
+        let allowed: Vec<String> = values.iter().map(value_to_string).collect();
+        if !allowed.iter().any(|item| item == value) {
+            return Err(format!("'{value}' is not allowed for --{name}, expected one of [{}]", allowed.join(", ")));
+        }
+    }
+
+    Ok(())
+}
+
+fn prompt(name: &str, param: &Param) -> String {
+    let message = format!("{name}:");
+    let help = param.info.clone().unwrap_or_default();
+
+    let result = match (&param.values, param.kind.as_ref().unwrap_or(&ParamKind::String)) {
+        (Some(values), _) => Select::new(&message, values.iter().map(value_to_string).collect()).with_help_message(&help).prompt(),
+        (None, ParamKind::Bool) => Confirm::new(&message).with_help_message(&help).prompt().map(|value| string!(value)),
+        (None, _) => {
+            let param = param.clone();
+            let owned = string!(name);
+
+            Text::new(&message)
+                .with_help_message(&help)
+                .with_validator(move |input: &str| match validate(&owned, &param, input) {
+                    Ok(_) => Ok(Validation::Valid),
+                    Err(err) => Ok(Validation::Invalid(err.into())),
+                })
+                .prompt()
+        }
+    };
+
+    match result {
+        Ok(value) => value,
+        Err(_) => crashln!("Aborting, no value given for --{name}."),
+    }
+}
+
+/// rejects parameters that neither the task nor any of its dependencies declare
+pub fn check(task: &str, provided: &BTreeMap<String, String>, accepted: &[String]) {
+    if let Some(name) = provided.keys().find(|name| !accepted.contains(*name) && *name != "help") {
+        crashln!("Task '{task}' does not accept the parameter --{name}. See 'maid {task} --help'.");
+    }
+}
+
+/// resolves declared parameters from the command line, defaults and interactive prompts
+pub fn resolve(task: &str, declared: &BTreeMap<String, Param>, provided: &BTreeMap<String, String>, is_dep: bool) -> BTreeMap<String, String> {
+    let mut params: BTreeMap<String, String> = BTreeMap::new();
+    let interactive = !is_dep && std::io::stdin().is_terminal();
+
+    for (name, param) in declared {
+        let value = match provided.get(name) {
+            Some(value) => Some(value.clone()),
+            None => param.default.as_ref().map(value_to_string),
+        };
+
+        let value = match value {
+            Some(value) => value,
+            None if param.required.unwrap_or(false) && interactive => prompt(name, param),
+            None if param.required.unwrap_or(false) => crashln!("Task '{task}' is missing the required parameter --{name}."),
+            None => string!(""),
+        };
+
+        if !value.is_empty() || provided.contains_key(name) {
+            if let Err(err) = validate(name, param, &value) {
+                crashln!("{err}");
+            }
+        }
+
+        log::debug!("Resolved param: {name} = '{value}'");
+        params.insert(name.clone(), value);
+    }
+
+    return params;
+}
+
+pub fn help(name: &str, task: &Tasks) {
+    let info = match &task.info {
+        Some(info) => format!("({info})").white(),
+        None => string!("(no description)").bright_red(),
+    };
+
+    println!("{} {}\n{} {}", name.bright_yellow().bold(), info, crate::helpers::string::arrow_icon(), task.script);
+
+    match &task.params {
+        Some(params) if !params.is_empty() => {
+            println!("\n{}", "Parameters".green().bold());
+
+            for (key, param) in params {
+                let kind = match param.kind.as_ref().unwrap_or(&ParamKind::String) {
+                    ParamKind::String => "string",
+                    ParamKind::Number => "number",
+                    ParamKind::Bool => "bool",
+                };
+
+                let mut details: Vec<String> = vec![];
+                if param.required.unwrap_or(false) {
+                    details.push(string!("required"));
+                }
+                if let Some(default) = &param.default {
+                    details.push(format!("default: {}", value_to_string(default)));
+                }
+                if let Some(values) = &param.values {
+                    details.push(format!("one of: {}", values.iter().map(value_to_string).collect::<Vec<_>>().join(", ")));
+                }
+
+                println!(
+                    " {} {} {}{}",
+                    format!("--{key}").bright_cyan(),
+                    format!("<{kind}>").white(),
+                    ternary!(details.is_empty(), string!(), format!("[{}] ", details.join(", ")).bright_blue().to_string()),
+                    param.info.clone().unwrap_or_default()
+                );
+            }
+        }
+        _ => println!("\n{}", "task has no parameters".white()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction, Command};
+
+    fn command() -> Command {
+        Command::new("maid")
+            .arg(Arg::new("path").short('p').long("path").default_value("maidfile"))
+            .arg(Arg::new("quiet").short('q').action(ArgAction::Count))
+            .arg(Arg::new("task").num_args(0..))
+    }
+
+    fn args(values: &[&str]) -> Vec<String> { values.iter().map(|value| string!(*value)).collect() }
+
+    fn number() -> Param {
+        Param {
+            kind: Some(ParamKind::Number),
+            info: None,
+            default: None,
+            values: None,
+            required: None,
+        }
+    }
+
+    #[test]
+    fn split_keeps_negative_values_of_declared_params() {
+        let (cli, params) = split(args(&["maid", "-p", "other", "build", "--offset", "-5"]), &mut command(), |path, task, name| path == "other" && task == "build" && name == "offset");
+
+        assert_eq!(cli, args(&["maid", "-p", "other", "build"]));
+        assert_eq!(params, args(&["--offset", "-5"]));
+    }
+
+    #[test]
+    fn split_leaves_dashed_flags_after_other_params_to_clap() {
+        let (cli, params) = split(args(&["maid", "build", "--force", "-q"]), &mut command(), |_, _, _| false);
+
+        assert_eq!(cli, args(&["maid", "build", "-q"]));
+        assert_eq!(params, args(&["--force"]));
+    }
+
+    #[test]
+    fn parse_accepts_negative_numbers() {
+        let declared = BTreeMap::from([(string!("offset"), number())]);
+        let (positional, provided) = parse(&args(&["build", "--offset", "-5"]), &declared);
+
+        assert_eq!(positional, args(&["build"]));
+        assert_eq!(provided.get("offset").map(String::as_str), Some("-5"));
+        assert!(validate("offset", &declared["offset"], "-5").is_ok());
+    }
+}
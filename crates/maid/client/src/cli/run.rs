@@ -1,41 +1,67 @@
 use crate::cli;
 use crate::helpers;
-use crate::shell::IntoArgs;
-use crate::structs::{Cache, Runner};
-use crate::table;
+use crate::shell::{IntoArgs, ParseError};
+use crate::structs::{Combination, Outcome, Runner};
+use crate::task;
 
 use colored::Colorize;
-use fs_extra::dir::get_size;
-use human_bytes::human_bytes;
-use macros_rs::{crashln, string};
+use indicatif::MultiProgress;
+use macros_rs::{crashln, fmtstr, string, ternary, then};
+use maid_protocol::table;
 use serde_json::json;
-use std::env;
 use std::io::Error;
 use std::path::Path;
 use std::process::{Child, Command, ExitStatus, Stdio};
-use std::time::Instant;
+use std::sync::{atomic::AtomicUsize, atomic::Ordering, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use text_placeholder::Template;
 
-fn run_script(runner: Runner) {
+/// program and arguments running `script` through the configured shell, `-c` is added unless the shell already ends with it
+fn shell_command(shell: &str, script: &str) -> Result<(String, Vec<String>), ParseError> {
+    let mut args = shell.try_into_args()?;
+
+    then!(args.last().map(String::as_str) != Some("-c"), args.push(string!("-c")));
+    args.push(string!(script));
+
+    let program = args.remove(0);
+    Ok((program, args))
+}
+
+fn run_script(runner: Runner) -> Outcome {
     let mut cmd: Child;
     let start = Instant::now();
+    let mut captured = String::new();
+    let mut failed_line: Option<(usize, String)> = None;
     let mut status_array: Vec<Result<ExitStatus, Error>> = vec![];
 
-    for string in runner.script.clone() {
+    // built once per task, reading the env files for every line would repeat their logs and work
+    let table = table::create(runner.maidfile.clone(), runner.args, runner.project.clone(), Some(runner.name), runner.vars, true);
+    let envs = table::envs(&table);
+
+    for (index, string) in runner.script.clone().into_iter().enumerate() {
         let start = Instant::now();
-        let table = table::create(runner.maidfile.clone(), runner.args, runner.project.clone());
         let script = Template::new_with_placeholder(string, "%{", "}").fill_with_hashmap(&table);
-        let (name, args) = match script.try_into_args() {
-            Ok(result) => {
-                let mut args = result.clone();
+        let (name, args) = match &runner.shell {
+            Some(shell) => match shell_command(shell, &script) {
+                Ok(command) => command,
+                Err(err) => {
+                    log::warn!("{err}");
+                    crashln!("Shell '{shell}' could not be parsed into args");
+                }
+            },
+            None => match script.try_into_args() {
+                Ok(result) => {
+                    let mut args = result.clone();
 
-                args.remove(0);
-                (result[0].clone(), args)
-            }
-            Err(err) => {
-                log::warn!("{err}");
-                crashln!("Script could not be parsed into args");
-            }
+                    args.remove(0);
+                    (result[0].clone(), args)
+                }
+                Err(err) => {
+                    log::warn!("{err}");
+                    crashln!("Script could not be parsed into args");
+                }
+            },
         };
 
         log::debug!("Original Script: {}", string);
@@ -44,17 +70,13 @@ fn run_script(runner: Runner) {
         log::info!("Execute Command: '{name} {}'", args.join(" "));
 
         let working_dir = runner.project.join(&Path::new(runner.path));
-        match env::set_current_dir(&working_dir) {
-            Ok(_) => {
-                log::info!("Working directory: {:?}", &working_dir);
-            }
-            Err(err) => {
-                crashln!("Failed to set working directory {:?}\nError: {:#?}", &working_dir, err);
-            }
+        match working_dir.is_dir() {
+            true => log::info!("Working directory: {:?}", &working_dir),
+            false => crashln!("Failed to set working directory {:?}", &working_dir),
         };
 
         if runner.is_dep {
-            cmd = match Command::new(&name).stdout(Stdio::null()).stderr(Stdio::null()).stdin(Stdio::null()).args(args.clone()).spawn() {
+            cmd = match Command::new(&name).current_dir(&working_dir).envs(envs.clone()).stdout(Stdio::piped()).stderr(Stdio::piped()).stdin(Stdio::null()).args(args.clone()).spawn() {
                 Ok(output) => output,
                 Err(err) => {
                     log::warn!("{err}");
@@ -62,7 +84,7 @@ fn run_script(runner: Runner) {
                 }
             };
         } else {
-            cmd = match Command::new(&name).args(args.clone()).stdout(Stdio::inherit()).stderr(Stdio::inherit()).stdin(Stdio::inherit()).spawn() {
+            cmd = match Command::new(&name).current_dir(&working_dir).envs(envs.clone()).args(args.clone()).stdout(Stdio::inherit()).stderr(Stdio::inherit()).stdin(Stdio::inherit()).spawn() {
                 Ok(output) => output,
                 Err(err) => {
                     log::warn!("{err}");
@@ -71,11 +93,25 @@ fn run_script(runner: Runner) {
             };
         }
 
-        let status = cmd.wait();
+        let status = match runner.is_dep {
+            true => cmd.wait_with_output().map(|output| {
+                captured.push_str(&String::from_utf8_lossy(&output.stdout));
+                captured.push_str(&String::from_utf8_lossy(&output.stderr));
+                output.status
+            }),
+            false => cmd.wait(),
+        };
+
         let exit_code = helpers::status::code(&status);
 
         status_array.push(status);
         log::debug!("Finished cmd: '{name} {}' with exit code: {:?} in {:.2?}", args.join(" "), exit_code, start.elapsed());
+
+        if exit_code != 0 && !runner.continue_on_error {
+            log::debug!("Stopping task {} at line {}", runner.name, index + 1);
+            failed_line = Some((index + 1, script));
+            break;
+        }
     }
 
     let status = match status_array.last() {
@@ -83,67 +119,39 @@ fn run_script(runner: Runner) {
         None => crashln!("Failed to fetch final status code."),
     };
 
-    let cache = match &runner.maidfile.tasks[runner.name].cache {
-        Some(cache) => cache.clone(),
-        None => Cache { path: string!(""), target: vec![] },
-    };
-
     let exit_code = helpers::status::code(status);
     let success = helpers::status::success(&status);
 
     if !runner.silent {
         if success {
             println!("\n{} {}", helpers::string::check_icon(), "finished task successfully".bright_green());
-            if !cache.path.trim().is_empty() && !cache.target.is_empty() {
-                for target in cache.target {
-                    let cache_file = format!(".maid/cache/{}/target/{}", runner.name, Path::new(&target).file_name().unwrap().to_str().unwrap());
-                    match std::fs::copy(Path::new(&target), cache_file.clone()) {
-                        Ok(_) => {
-                            println!(
-                                "{} ({})",
-                                format!("saved target '{}' to cache", target).bright_magenta(),
-                                format!("{}", human_bytes(get_size(cache_file.clone()).unwrap() as f64).white())
-                            );
-                            log::debug!("saved target file {}", target)
-                        }
-                        Err(err) => {
-                            log::warn!("{err}");
-                            log::debug!("path: {}", target);
-                            crashln!("Cannot save target file.");
-                        }
-                    };
-                }
-            }
             println!("{} took {}", runner.name.white(), format!("{:.2?}", start.elapsed()).yellow());
         } else {
-            println!("\n{} {} {}", helpers::string::cross_icon(), "exited with status code".bright_red(), format!("{}", exit_code).red());
+            match &failed_line {
+                Some((line, script)) => println!(
+                    "\n{} {} {}",
+                    helpers::string::cross_icon(),
+                    format!("line {line} '{script}' exited with status code").bright_red(),
+                    format!("{}", exit_code).red()
+                ),
+                None => println!("\n{} {} {}", helpers::string::cross_icon(), "exited with status code".bright_red(), format!("{}", exit_code).red()),
+            };
             println!("{} took {}", runner.name.white(), format!("{:.2?}", start.elapsed()).yellow());
         }
-    } else {
-        if success {
-            if !cache.path.trim().is_empty() && !cache.target.is_empty() {
-                for target in cache.target {
-                    let cache_file = format!(".maid/cache/{}/target/{}", runner.name, Path::new(&target).file_name().unwrap().to_str().unwrap());
-                    match std::fs::copy(Path::new(&target), cache_file.clone()) {
-                        Ok(_) => println!(
-                            "{} {}{}{}",
-                            helpers::string::add_icon(),
-                            format!("{}", target).bright_green(),
-                            helpers::string::seperator(),
-                            format!("{}", human_bytes(get_size(cache_file.clone()).unwrap() as f64).bright_cyan())
-                        ),
-                        Err(err) => {
-                            log::error!("{err}");
-                            log::debug!("path: {}", target);
-                        }
-                    };
-                }
-            }
-        }
+    }
+
+    let outcome = match exit_code {
+        0 => Outcome::ok(runner.name),
+        code => Outcome::failed(runner.name, code, failed_line),
+    };
+
+    Outcome {
+        output: ternary!(runner.is_dep, Some(captured), None),
+        ..outcome
     }
 }
 
-pub fn task(task: cli::Task) {
+pub fn task(task: cli::Task) -> Outcome {
     let mut script: Vec<&str> = vec![];
 
     if task.script.is_str() {
@@ -170,14 +178,144 @@ pub fn task(task: cli::Task) {
         helpers::status::error(task.script.type_str())
     }
 
-    run_script(Runner {
+    let shell = match &task.maidfile.tasks[&task.name].shell {
+        Some(shell) => Some(shell.clone()),
+        None => task.maidfile.project.as_ref().and_then(|project| project.shell.clone()),
+    }
+    .filter(|shell| !shell.trim().is_empty());
+
+    return run_script(Runner {
         name: &task.name,
         path: &task.path,
         args: &task.args,
+        vars: &task.vars,
         silent: task.silent,
         is_dep: task.is_dep,
+        continue_on_error: task.maidfile.tasks[&task.name].continue_on_error.unwrap_or(false),
         project: &task.project,
         maidfile: &task.maidfile,
+        shell,
         script,
     });
 }
+
+/// prints the exit code and duration of every combination that ran, in matrix order
+pub fn summary(name: &str, combinations: &[Combination], results: &[(usize, i32, Duration)], elapsed: Duration) {
+    let passed = results.iter().filter(|(_, code, _)| *code == 0).count();
+    let total = combinations.len();
+
+    println!("\n{}", format!("matrix results for {name}").green().bold());
+
+    for (index, code, elapsed) in results.iter() {
+        match code {
+            0 => println!(" {} {} {}", helpers::string::check_icon(), combinations[*index].label.white(), format!("({elapsed:.2?})").yellow()),
+            code => println!(
+                " {} {} {} {}",
+                helpers::string::cross_icon(),
+                combinations[*index].label.white(),
+                format!("exited with status code {code}").bright_red(),
+                format!("({elapsed:.2?})").yellow()
+            ),
+        }
+    }
+
+    println!(
+        "{} {} in {}",
+        ternary!(passed == total, helpers::string::check_icon(), helpers::string::cross_icon()),
+        format!("{passed}/{total} combinations passed").white(),
+        format!("{elapsed:.2?}").yellow()
+    );
+}
+
+pub fn matrix(task: cli::Task, combinations: Vec<Combination>, parallel: bool, jobs: usize) -> Outcome {
+    let start = Instant::now();
+    let total = combinations.len();
+    let results: Mutex<Vec<(usize, Outcome, Duration)>> = Mutex::new(vec![]);
+
+    let run_instance = |index: usize, capture: bool| -> Outcome {
+        let mut instance = task.clone();
+        let combination = &combinations[index];
+
+        instance.vars.extend(combination.vars.clone());
+        instance.silent = instance.silent || capture;
+        instance.is_dep = instance.is_dep || capture;
+
+        let start = Instant::now();
+        let outcome = self::task(instance);
+
+        results.lock().unwrap().push((index, outcome.clone(), start.elapsed()));
+        return outcome;
+    };
+
+    if parallel {
+        let next = AtomicUsize::new(0);
+        let multi = MultiProgress::new();
+        let ticks = vec!["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+        let template = fmtstr!("{{prefix:.white}} {{spinner:.yellow}}{{msg}} {}", "({elapsed})".bright_cyan());
+
+        thread::scope(|scope| {
+            for _ in 0..jobs.min(total) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    then!(index >= total, break);
+
+                    let pb = multi.add(task::progress::init(ticks.clone(), template, 80));
+                    pb.set_prefix(format!("[{}/{total}]", index + 1));
+                    pb.set_message(fmtstr!("{} {}", "running".bright_yellow(), combinations[index].label));
+
+                    let outcome = run_instance(index, true);
+                    pb.finish_and_clear();
+
+                    if !task.silent && !task.is_dep {
+                        multi.suspend(|| {
+                            println!("{} {}", helpers::string::arrow_icon(), format!("[{}]", combinations[index].label).bright_cyan());
+                            print!("{}", outcome.output.unwrap_or_default());
+                        });
+                    }
+                });
+            }
+        });
+    } else {
+        for (index, combination) in combinations.iter().enumerate() {
+            if !task.silent && !task.is_dep {
+                println!("\n{} {}", helpers::string::arrow_icon(), format!("[{}/{total}] {}", index + 1, combination.label).bright_cyan());
+            }
+
+            run_instance(index, false);
+        }
+    }
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _, _)| *index);
+
+    if !task.is_dep {
+        let codes: Vec<(usize, i32, Duration)> = results.iter().map(|(index, outcome, elapsed)| (*index, outcome.code, *elapsed)).collect();
+        summary(&task.name, &combinations, &codes, start.elapsed());
+    }
+
+    match results.into_iter().find(|(_, outcome, _)| outcome.code != 0) {
+        Some((index, outcome, _)) => Outcome {
+            name: format!("{} [{}]", task.name, combinations[index].label),
+            ..outcome
+        },
+        None => Outcome::ok(&task.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> { values.iter().map(|value| string!(*value)).collect() }
+
+    #[test]
+    fn shell_command_adds_c_after_shell_flags() {
+        assert_eq!(shell_command("bash -e", "echo hi").unwrap(), (string!("bash"), args(&["-e", "-c", "echo hi"])));
+        assert_eq!(shell_command("sh", "echo hi").unwrap(), (string!("sh"), args(&["-c", "echo hi"])));
+    }
+
+    #[test]
+    fn shell_command_keeps_an_explicit_c() {
+        assert_eq!(shell_command("bash -o pipefail -c", "echo hi").unwrap(), (string!("bash"), args(&["-o", "pipefail", "-c", "echo hi"])));
+    }
+}
@@ -1,19 +1,21 @@
 use crate::cli;
 use crate::helpers;
 use crate::parse;
-use crate::structs;
-use crate::table;
+use crate::structs::{self, Outcome};
 
 use colored::Colorize;
 use inquire::Select;
 use macros_rs::{string, ternary};
+use maid_protocol::table;
+use std::collections::BTreeMap;
 use text_placeholder::Template;
 
 pub fn json(path: &String, args: &Vec<String>, hydrate: &bool) {
     let values = helpers::maidfile::merge(path);
     let project_root = parse::file::find_maidfile_root(path);
-    let json = values.clone().to_json();
-    let table = table::create(values.clone(), args, project_root);
+    let json = helpers::maidfile::to_json(&values);
+    let vars = BTreeMap::new();
+    let table = table::create(values.clone(), args, project_root, None, &vars, true);
     let hydrated_json = Template::new_with_placeholder(&json, "%{", "}").fill_with_hashmap(&table);
 
     println!("{}", ternary!(hydrate.clone(), hydrated_json, json))
@@ -21,7 +23,7 @@ pub fn json(path: &String, args: &Vec<String>, hydrate: &bool) {
 
 pub struct List;
 impl List {
-    pub fn all(path: &String, silent: bool, log_level: Option<log::Level>) {
+    pub fn all(path: &String, silent: bool, log_level: Option<log::Level>, jobs: usize) -> Outcome {
         let values = helpers::maidfile::merge(path);
         let mut options: Vec<_> = values
             .tasks
@@ -60,13 +62,16 @@ impl List {
         match Select::new("Select a task to run:", options).prompt() {
             Ok(task) => {
                 log::debug!("Starting {}", task.name);
-                cli::exec(&String::from(task.name), &vec![String::from("")], &path, silent, false, false, log_level);
+                cli::exec(&String::from(task.name), &vec![String::from("")], &path, silent, false, false, log_level, jobs)
+            }
+            Err(_) => {
+                println!("{}", "Aborting...".white());
+                Outcome::ok("")
             }
-            Err(_) => println!("{}", "Aborting...".white()),
         }
     }
 
-    pub fn remote(path: &String, silent: bool, log_level: Option<log::Level>) {
+    pub fn remote(path: &String, silent: bool, log_level: Option<log::Level>, jobs: usize) -> Outcome {
         let values = helpers::maidfile::merge(path);
         let mut options: Vec<_> = values
             .tasks
@@ -99,9 +104,12 @@ impl List {
         match Select::new("Select a remote task to run:", options).prompt() {
             Ok(task) => {
                 log::debug!("Starting {}", task.name);
-                cli::exec(&String::from(task.name), &vec![String::from("")], &path, silent, false, true, log_level);
+                cli::exec(&String::from(task.name), &vec![String::from("")], &path, silent, false, true, log_level, jobs)
+            }
+            Err(_) => {
+                println!("{}", "Aborting...".white());
+                Outcome::ok("")
             }
-            Err(_) => println!("{}", "Aborting...".white()),
         }
     }
 }
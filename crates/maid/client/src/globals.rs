@@ -1,6 +1,8 @@
 use global_placeholders::init;
 
 pub fn init() {
-    init!("maid.cache_dir", ".maid/cache/{}/target");
+    init!("maid.cache_dir", ".maid/cache");
+    init!("maid.cache_objects", ".maid/cache/objects");
+    init!("maid.cache_entries", ".maid/cache/entries/{}");
     init!("maid.temp_dir", ".maid/temp");
 }
@@ -1,5 +1,9 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
 use macros_rs::crashln;
+use std::collections::BTreeSet;
 use std::env;
+use std::path::{Path, PathBuf};
 
 pub fn get_current_working_dir() -> String {
     match env::current_dir() {
@@ -9,3 +13,60 @@ pub fn get_current_working_dir() -> String {
         }
     }
 }
+
+pub fn glob_set(patterns: &Vec<String>) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        match Glob::new(pattern) {
+            Ok(glob) => builder.add(glob),
+            Err(err) => crashln!("Invalid glob pattern '{pattern}'.\n{err}"),
+        };
+    }
+
+    match builder.build() {
+        Ok(set) => set,
+        Err(err) => crashln!("Unable to build glob patterns.\n{err}"),
+    }
+}
+
+pub fn is_glob(pattern: &str) -> bool { pattern.contains(['*', '?', '[', '{']) }
+
+fn walk(path: &Path, gitignore: bool) -> Vec<PathBuf> {
+    WalkBuilder::new(path)
+        .standard_filters(gitignore)
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != ".maid")
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// resolves paths and glob patterns against root into a sorted list of relative file paths,
+/// glob matches skip gitignored files when `gitignore` is set
+pub fn expand(root: &Path, patterns: &Vec<String>, gitignore: bool) -> Vec<PathBuf> {
+    let mut files: BTreeSet<PathBuf> = BTreeSet::new();
+    let globs: Vec<String> = patterns.iter().filter(|pattern| is_glob(pattern)).cloned().collect();
+
+    for pattern in patterns.iter().filter(|pattern| !is_glob(pattern)) {
+        let path = root.join(pattern);
+
+        match path.is_dir() {
+            true => files.extend(walk(&path, false)),
+            false if path.is_file() => {
+                files.insert(path);
+            }
+            false => log::warn!("path '{pattern}' does not exist"),
+        }
+    }
+
+    if !globs.is_empty() {
+        let set = glob_set(&globs);
+        files.extend(walk(root, gitignore).into_iter().filter(|path| path.strip_prefix(root).is_ok_and(|relative| set.is_match(relative))));
+    }
+
+    files.into_iter().filter_map(|path| path.strip_prefix(root).map(Path::to_path_buf).ok()).collect()
+}
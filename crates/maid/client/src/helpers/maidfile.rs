@@ -20,14 +20,12 @@ pub fn merge(path: &String) -> Maidfile {
     return values;
 }
 
-impl Maidfile {
-    pub fn to_json(&self) -> String {
-        match serde_json::to_string(&self) {
-            Ok(contents) => contents,
-            Err(err) => {
-                log::warn!("{err}");
-                crashln!("Cannot read maidfile.");
-            }
+pub fn to_json(values: &Maidfile) -> String {
+    match serde_json::to_string(values) {
+        Ok(contents) => contents,
+        Err(err) => {
+            log::warn!("{err}");
+            crashln!("Cannot read maidfile.");
         }
     }
 }
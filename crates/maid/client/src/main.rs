@@ -5,13 +5,11 @@ mod parse;
 mod server;
 mod shell;
 mod structs;
-mod table;
 mod task;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
 use macros_rs::str;
-use std::path::Path;
 
 #[derive(Parser)]
 #[command(version = str!(cli::get_version(false)))]
@@ -21,6 +19,8 @@ struct Cli {
     task: Vec<String>,
     #[arg(global = true, short, long, default_value_t = String::from("maidfile"), help = "maidfile path")]
     path: String,
+    #[arg(global = true, short, long, help = "Number of dependencies to run in parallel")]
+    jobs: Option<usize>,
     #[command(subcommand)]
     command: Option<Commands>,
     #[clap(flatten)]
@@ -53,8 +53,16 @@ enum Butler {
     Init,
     /// Clear maid cache
     Clean,
+    /// Inspect and prune the local cache
+    Cache {
+        #[command(subcommand)]
+        action: Cache,
+    },
     /// Watch maidfile task
-    Watch,
+    Watch {
+        #[arg(default_value = "", hide_default_value = true)]
+        task: Vec<String>,
+    },
     /// Check/Retrieve updates
     Update,
     /// Return the maidfile in json
@@ -64,6 +72,19 @@ enum Butler {
     },
 }
 
+#[derive(Subcommand)]
+enum Cache {
+    /// Show local cache usage
+    Stats,
+    /// Evict least recently used entries over the size limit
+    Prune {
+        #[arg(long, help = "Size to prune down to, e.g. 500MB")]
+        limit: Option<String>,
+    },
+    /// List cache entries
+    Ls { task: Option<String> },
+}
+
 #[derive(Subcommand)]
 enum Remote {
     /// List all remote maidfile tasks
@@ -72,30 +93,75 @@ enum Remote {
     Connect,
     /// Clear remote maid cache
     Clean,
+    /// List recent remote jobs
+    Jobs {
+        /// Number of jobs to show
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print the log of a remote job
+    Logs {
+        /// Job id or unique prefix
+        id: String,
+    },
+    /// Cancel a running remote job
+    Cancel {
+        /// Job id or unique prefix
+        id: String,
+    },
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let (argv, params) = cli::params::split(std::env::args().collect(), &mut Cli::command(), cli::params::takes_value);
+    let mut cli = Cli::parse_from(argv);
+
+    match &mut cli.command {
+        Some(Commands::Remote { task, .. }) => task.extend(params),
+        Some(Commands::Butler { internal: Butler::Watch { task } }) => task.extend(params),
+        _ => cli.task.extend(params),
+    };
 
     globals::init();
     env_logger::Builder::new().filter_level(cli.verbose.log_level_filter()).init();
 
+    let jobs = task::scheduler::jobs(cli.jobs);
+
     match &cli.command {
         Some(Commands::Butler { internal }) => match internal {
             Butler::Json { hydrate } => cli::tasks::json(&cli.path, &cli.task, hydrate),
             Butler::Info => cli::info(&cli.path),
             Butler::Clean => cli::butler::clean(),
+            Butler::Cache { action } => match action {
+                Cache::Stats => cli::butler::cache_stats(&cli.path),
+                Cache::Prune { limit } => cli::butler::cache_prune(&cli.path, limit),
+                Cache::Ls { task } => cli::butler::cache_ls(task),
+            },
             Butler::Init => cli::butler::init(),
-            Butler::Watch => cli::butler::watch(Path::new("src")),
+            Butler::Watch { task } => cli::butler::watch(&cli.path, task, jobs),
             Butler::Update => cli::butler::update(),
-            Butler::Tasks => cli::tasks::List::all(&cli.path, cli.verbose.is_silent(), cli.verbose.log_level()),
+            Butler::Tasks => {
+                let outcome = cli::tasks::List::all(&cli.path, cli.verbose.is_silent(), cli.verbose.log_level(), jobs);
+                std::process::exit(outcome.code);
+            }
         },
         Some(Commands::Remote { task, server }) => match server {
             Some(Remote::Connect) => server::cli::connect(&cli.path),
             Some(Remote::Clean) => server::cli::connect(&cli.path),
-            Some(Remote::List) => cli::tasks::List::remote(&cli.path, cli.verbose.is_silent(), cli.verbose.log_level()),
-            None => cli::exec(task[0].trim(), &task, &cli.path, cli.verbose.is_silent(), false, true, cli.verbose.log_level()),
+            Some(Remote::Jobs { limit }) => server::cli::jobs(&cli.path, *limit),
+            Some(Remote::Logs { id }) => server::cli::logs(&cli.path, id),
+            Some(Remote::Cancel { id }) => server::cli::cancel(&cli.path, id),
+            Some(Remote::List) => {
+                let outcome = cli::tasks::List::remote(&cli.path, cli.verbose.is_silent(), cli.verbose.log_level(), jobs);
+                std::process::exit(outcome.code);
+            }
+            None => {
+                let outcome = cli::exec(task[0].trim(), &task, &cli.path, cli.verbose.is_silent(), false, true, cli.verbose.log_level(), jobs);
+                std::process::exit(outcome.code);
+            }
         },
-        None => cli::exec(cli.task[0].trim(), &cli.task, &cli.path, cli.verbose.is_silent(), false, false, cli.verbose.log_level()),
+        None => {
+            let outcome = cli::exec(cli.task[0].trim(), &cli.task, &cli.path, cli.verbose.is_silent(), false, false, cli.verbose.log_level(), jobs);
+            std::process::exit(outcome.code);
+        }
     }
 }
@@ -0,0 +1,125 @@
+use crate::server;
+use crate::structs::{Cache, CacheEntry, Maidfile};
+use crate::task;
+
+use colored::Colorize;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use global_placeholders::global;
+use human_bytes::human_bytes;
+use reqwest::{blocking::Client, StatusCode};
+use std::{fs::File, path::Path, time::Duration};
+use tar::{Archive, Builder, Header};
+use uuid::Uuid;
+
+fn client() -> Client { Client::builder().timeout(Duration::from_secs(300)).build().unwrap_or_default() }
+
+/// the server keeps entries per project and only serves them to tokens scoped to it
+fn project(values: &Maidfile) -> String { values.project.as_ref().and_then(|project| project.name.clone()).unwrap_or_default() }
+
+/// remote caching is opt-in through `[project.cache] remote = true` and needs a configured server
+pub fn enabled(values: &Maidfile) -> bool {
+    let remote = values.project.as_ref().and_then(|project| project.cache.as_ref()).and_then(|cache| cache.remote).unwrap_or(false);
+    return remote && !server::parse::address(values).is_empty();
+}
+
+fn pack(entry: &CacheEntry) -> std::io::Result<Vec<u8>> {
+    let mut builder = Builder::new(GzEncoder::new(vec![], Compression::default()));
+
+    for file in entry.files.iter() {
+        let mut header = Header::new_gnu();
+
+        header.set_size(file.size);
+        header.set_mode(file.mode.unwrap_or(0o644) & 0o7777);
+        header.set_mtime(file.mtime.max(0) as u64);
+        builder.append_data(&mut header, &file.path, File::open(task::cache::blob_path(&file.hash))?)?;
+    }
+
+    builder.into_inner()?.finish()
+}
+
+/// downloads an entry from the server and adds it to the local store, any failure is treated as a miss
+pub fn pull(values: &Maidfile, task: &str, key: &str, cache: &Cache, silent: bool) -> Option<CacheEntry> {
+    let address = server::parse::address(values);
+    let token = server::parse::token(values);
+
+    let response = match client().get(format!("{address}/api/cache/{key}")).query(&[("project", project(values))]).header("Authorization", format!("Bearer {token}")).send() {
+        Ok(response) => response,
+        Err(err) => {
+            log::warn!("{err}");
+            println!("{}", "remote cache is unavailable, continuing without it".yellow());
+            return None;
+        }
+    };
+
+    match response.status() {
+        StatusCode::OK => log::debug!("remote cache hit for {key}"),
+        StatusCode::NOT_FOUND => {
+            log::debug!("remote cache miss for {key}");
+            return None;
+        }
+        status => {
+            log::warn!("remote cache returned {status}");
+            return None;
+        }
+    };
+
+    let bytes = match response.bytes() {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("{err}");
+            return None;
+        }
+    };
+
+    let temp = Path::new(&global!("maid.temp_dir")).join(Uuid::new_v4().to_string());
+    let mut archive = Archive::new(GzDecoder::new(bytes.as_ref()));
+
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_permissions(true);
+
+    if let Err(err) = archive.unpack(&temp) {
+        log::warn!("{err}");
+        std::fs::remove_dir_all(&temp).ok();
+        return None;
+    }
+
+    let entry = task::cache::import(task, key, cache, &temp);
+    std::fs::remove_dir_all(&temp).ok();
+
+    if !silent {
+        println!("{} ({})", "pulled cache from server".magenta(), human_bytes(bytes.len() as f64).white());
+    }
+
+    return Some(entry);
+}
+
+/// uploads a stored entry so other machines can reuse it, failures only warn
+pub fn push(values: &Maidfile, entry: &CacheEntry, silent: bool) {
+    let address = server::parse::address(values);
+    let token = server::parse::token(values);
+
+    let archive = match pack(entry) {
+        Ok(archive) => archive,
+        Err(err) => {
+            log::warn!("{err}");
+            return println!("{}", "unable to pack cache for the server".yellow());
+        }
+    };
+
+    let size = archive.len();
+    match client().put(format!("{address}/api/cache/{}", entry.key)).query(&[("project", project(values))]).header("Authorization", format!("Bearer {token}")).body(archive).send() {
+        Ok(response) if response.status().is_success() => {
+            if !silent {
+                println!("{} ({})", "uploaded cache to server".bright_magenta(), human_bytes(size as f64).white());
+            }
+        }
+        Ok(response) => {
+            log::warn!("remote cache returned {}", response.status());
+            println!("{}", "unable to upload cache to the server".yellow());
+        }
+        Err(err) => {
+            log::warn!("{err}");
+            println!("{}", "unable to upload cache to the server".yellow());
+        }
+    };
+}
@@ -1,14 +1,66 @@
+use crate::cli;
 use crate::helpers;
 use crate::server;
-use crate::structs::{ConnectionData, ConnectionInfo, Kind, Level, Maidfile, Task, Websocket};
+use crate::structs::{Combination, ConnectionData, ConnectionInfo, Kind, Level, Maidfile, Response, Task};
+use crate::task;
 
 use colored::Colorize;
-use macros_rs::{crashln, fmtstr};
-use reqwest::blocking::Client;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use macros_rs::{crashln, fmtstr, string, then};
+use maid_protocol::{health, jobs::Job, jobs::JobStatus, Chunk, ClientMessage, FileEntry, CHUNK_SIZE, PROTOCOL_VERSION, VERSION};
+use reqwest::{blocking::Client, Method};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fs::File, net::TcpStream, sync::Arc, time::Duration, time::Instant};
 use tungstenite::protocol::frame::{coding::CloseCode::Normal, CloseFrame};
-use tungstenite::{client::connect_with_config, client::IntoClientRequest, protocol::WebSocketConfig, Message};
+use tungstenite::{client::connect_with_config, client::IntoClientRequest, protocol::WebSocketConfig, stream::MaybeTlsStream, Error, Message, WebSocket};
 
-fn health(client: Client, values: Maidfile) -> server::api::health::Route {
+/// times a dropped upload is resumed on a new connection before giving up
+const RECONNECTS: u32 = 3;
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// an archive of blobs, identified to the server by its blake3 hash
+struct Archive {
+    path: String,
+    id: String,
+    size: u64,
+    missing: Vec<String>,
+}
+
+/// the task's `push` paths, plus the archive of the blobs the server asked for last
+struct Workspace {
+    files: Vec<FileEntry>,
+    blobs: Option<Archive>,
+}
+
+impl Workspace {
+    /// the archive for a list of missing blobs, reused across reconnects so its upload can resume
+    fn blobs(&mut self, missing: Vec<String>) -> Result<&Archive, anyhow::Error> {
+        if self.blobs.as_ref().is_some_and(|archive| archive.missing == missing) {
+            return Ok(self.blobs.as_ref().unwrap());
+        }
+
+        if let Some(archive) = self.blobs.take() {
+            server::file::remove_tar(&archive.path);
+        }
+
+        let path = server::file::write_blobs(&self.files, &missing)?;
+        let (id, size) = server::file::checksum(&path)?;
+
+        Ok(self.blobs.insert(Archive { path, id, size, missing }))
+    }
+}
+
+/// how a connection to the gateway ended
+enum Session {
+    Done(i32),
+    /// dropped while uploading, a new connection resumes the upload
+    Interrupted(anyhow::Error),
+    Failed(anyhow::Error),
+}
+
+fn health(client: Client, values: Maidfile) -> health::Route {
     let address = server::parse::address(&values);
     let token = server::parse::token(&values);
 
@@ -21,7 +73,7 @@ fn health(client: Client, values: Maidfile) -> server::api::health::Route {
     };
 
     let body =
-        match response.json::<server::api::health::Route>() {
+        match response.json::<health::Route>() {
             Ok(body) => body,
             Err(err) => {
                 log::warn!("{err}");
@@ -54,7 +106,7 @@ pub fn connect(path: &String) {
     );
 }
 
-pub fn remote(task: Task) {
+pub fn remote(task: Task) -> i32 {
     let mut script: Vec<&str> = vec![];
 
     if task.script.is_str() {
@@ -83,7 +135,7 @@ pub fn remote(task: Task) {
 
     let client = Client::new();
     let body = health(client, task.maidfile.clone());
-    let (_, websocket, token, host, port) = server::parse::all(task.maidfile.clone());
+    let (_, _, _, host, port) = server::parse::all(task.maidfile.clone());
 
     crate::log!(Level::Info, "connecting to {host}:{port}");
 
@@ -93,48 +145,264 @@ pub fn remote(task: Task) {
         crate::log!(Level::Warning, "failed to connect");
     }
 
-    let websocket_config = WebSocketConfig {
-        max_frame_size: Some(314572800),
-        ..Default::default()
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler = Arc::clone(&interrupted);
+
+    if let Err(err) = ctrlc::set_handler(move || {
+        then!(handler.swap(true, Ordering::SeqCst), std::process::exit(130));
+    }) {
+        log::warn!("{err}");
+    }
+
+    let files = match server::file::manifest(&task.remote.as_ref().unwrap().push) {
+        Ok(files) => files,
+        Err(err) => {
+            crashln!("Unable to read the files to push.\nError: {err}")
+        }
     };
 
-    let mut request = websocket.into_client_request().expect("Can't connect");
-    request.headers_mut().insert("Authorization", fmtstr!("Bearer {token}").parse().unwrap());
+    match &task.maidfile.tasks[&task.name].matrix {
+        Some(matrix) if !matrix.is_empty() => self::matrix(&task, task::matrix::expand(matrix), &script, &files, &interrupted),
+        _ => build(&task, script.iter().map(|&line| line.to_string()).collect(), files, &interrupted),
+    }
+}
 
-    let (mut socket, response) = connect_with_config(request, Some(websocket_config), 3).expect("Can't connect");
-    log::debug!("response code: {}", response.status());
+/// runs every combination as its own build, one after another, so each gets an exit code in the summary
+///
+/// remote combinations never run in parallel, their output is streamed straight from the server
+fn matrix(task: &Task, combinations: Vec<Combination>, script: &[&str], files: &[FileEntry], interrupted: &AtomicBool) -> i32 {
+    let start = Instant::now();
+    let total = combinations.len();
+    let mut results: Vec<(usize, i32, Duration)> = vec![];
+
+    if task.maidfile.tasks[&task.name].parallel.unwrap_or(false) {
+        crate::log!(Level::Notice, "running {total} combinations one after another, 'parallel' only applies to local tasks");
+    }
+
+    for (index, combination) in combinations.iter().enumerate() {
+        then!(interrupted.load(Ordering::SeqCst), break);
+        println!("\n{} {}", helpers::string::arrow_icon(), format!("[{}/{total}] {}", index + 1, combination.label).bright_cyan());
+
+        let lines = script.iter().map(|line| task::matrix::fill(line, &combination.vars)).collect();
+        let started = Instant::now();
+        let code = build(task, lines, files.to_vec(), interrupted);
+
+        results.push((index, code, started.elapsed()));
+    }
+
+    if !task.is_dep {
+        cli::run::summary(&task.name, &combinations, &results, start.elapsed());
+    }
+
+    results.iter().map(|(_, code, _)| *code).find(|code| *code != 0).unwrap_or(0)
+}
+
+/// one build on the server, reconnecting to resume a dropped upload
+fn build(task: &Task, script: Vec<String>, files: Vec<FileEntry>, interrupted: &AtomicBool) -> i32 {
+    let (_, websocket, token, _, _) = server::parse::all(task.maidfile.clone());
 
     let connection_data = ConnectionData {
         info: ConnectionInfo {
             name: task.name.clone(),
             args: task.args.clone(),
             remote: task.remote.clone().unwrap(),
-            script: script.clone().iter().map(|&s| s.to_string()).collect(),
+            script,
+            vars: task.vars.clone(),
         },
         maidfile: task.maidfile.clone(),
     };
 
-    let file_name = match server::file::write_tar(&task.remote.unwrap().push) {
-        Ok(name) => name,
-        Err(err) => {
-            crashln!("Unable to create archive.\nError: {err}")
+    let mut workspace = Workspace { files, blobs: None };
+
+    let mut attempt = 0;
+    let (mut socket, exit_code) = loop {
+        let mut socket = match gateway(&websocket, &token) {
+            Ok(socket) => socket,
+            Err(err) if attempt > 0 && attempt < RECONNECTS => {
+                attempt += 1;
+                crate::log!(Level::Warning, "unable to reconnect ({err}), retrying ({attempt}/{RECONNECTS})");
+                std::thread::sleep(Duration::from_secs(1 << attempt));
+                continue;
+            }
+            Err(err) => {
+                log::warn!("{err}");
+                crashln!("Unable to connect to the maid server. Is it up?");
+            }
+        };
+
+        match session(&mut socket, &connection_data, &mut workspace, interrupted) {
+            Session::Done(code) => break (socket, code),
+            Session::Interrupted(err) if attempt < RECONNECTS => {
+                attempt += 1;
+                crate::log!(Level::Warning, "connection lost during upload ({err}), reconnecting ({attempt}/{RECONNECTS})");
+                std::thread::sleep(Duration::from_secs(1 << attempt));
+            }
+            Session::Interrupted(err) | Session::Failed(err) => {
+                crate::log!(Level::Fatal, "{err}");
+                break (socket, 1);
+            }
         }
     };
 
-    log::debug!("sending information");
-    socket.send(Message::Text(serde_json::to_string(&connection_data).unwrap())).unwrap();
+    if let Some(archive) = workspace.blobs {
+        server::file::remove_tar(&archive.path);
+    }
+
+    let reason = match exit_code {
+        0 => {
+            println!("\n{} {}", helpers::string::check_icon(), "finished task successfully".bright_green());
+            string!("finished task successfully")
+        }
+        code => {
+            println!("\n{} {} {}", helpers::string::cross_icon(), "exited with status code".bright_red(), format!("{code}").red());
+            format!("exited with status code {code}")
+        }
+    };
+
+    println!("{}", "removed temporary archive".bright_magenta());
+
+    if let Err(err) = socket.close(Some(CloseFrame {
+        code: Normal,
+        reason: std::borrow::Cow::Owned(reason),
+    })) {
+        log::warn!("Unable to close socket: {err}")
+    };
+
+    return exit_code;
+}
+
+/// opens the gateway websocket, with reads timing out so a ctrl-c can be forwarded while waiting for output
+fn gateway(websocket: &str, token: &str) -> Result<Socket, anyhow::Error> {
+    // artifacts still come back as a single frame
+    let websocket_config = WebSocketConfig {
+        max_frame_size: Some(314572800),
+        ..Default::default()
+    };
+
+    let mut request = websocket.into_client_request()?;
+    request.headers_mut().insert("Authorization", fmtstr!("Bearer {token}").parse().unwrap());
+
+    let (socket, response) = connect_with_config(request, Some(websocket_config), 3)?;
+    log::debug!("response code: {}", response.status());
+
+    let tcp: Option<&TcpStream> = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => Some(stream),
+        MaybeTlsStream::Rustls(stream) => Some(&stream.sock),
+        _ => None,
+    };
+
+    if let Some(Err(err)) = tcp.map(|stream| stream.set_read_timeout(Some(Duration::from_millis(200)))) {
+        log::warn!("Unable to set socket timeout: {err}");
+    }
+
+    Ok(socket)
+}
+
+/// streams the archive from `offset` in checksummed chunks, stopping early on ctrl-c
+fn upload(socket: &mut Socket, archive: &Archive, offset: u64, interrupted: &AtomicBool) -> Result<(), anyhow::Error> {
+    let mut file = File::open(&archive.path)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut position = file.seek(SeekFrom::Start(offset))?;
+
+    let progress = ProgressBar::new(archive.size);
+    progress.set_style(ProgressStyle::with_template("{spinner:.yellow} uploading [{bar:30.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})").unwrap().progress_chars("=> "));
+    progress.set_position(offset);
+
+    while position < archive.size && !interrupted.load(Ordering::SeqCst) {
+        let read = file.read(&mut buffer)?;
+        then!(read == 0, break);
+
+        socket.send(Message::Binary(Chunk::new(position, &buffer[..read]).encode()))?;
+        position += read as u64;
+        progress.set_position(position);
+    }
+
+    progress.finish_and_clear();
+    Ok(())
+}
+
+/// one connection to the gateway: handshake, task, upload and build output
+fn session(socket: &mut Socket, connection_data: &ConnectionData, workspace: &mut Workspace, interrupted: &AtomicBool) -> Session {
+    log::debug!("sending handshake");
+    if let Err(err) = socket.send(Message::Text(ClientMessage::hello().into())) {
+        return Session::Failed(err.into());
+    }
+
+    let mut cancelling = false;
+    let mut uploading = false;
+    let mut handshake = Some(Instant::now());
+
+    let dropped = |err: anyhow::Error, uploading: bool| match uploading {
+        true => Session::Interrupted(err),
+        false => Session::Failed(err),
+    };
 
     loop {
-        match socket.read() {
-            Ok(Message::Text(text)) => {
-                if let Ok(Websocket { message, kind, level, .. }) = serde_json::from_str::<Websocket>(&text) {
-                    match kind {
-                        Kind::Done => break,
-                        Kind::Message => crate::log!(level, "{}", message.unwrap()),
-                        Kind::Binary => socket.send(Message::Binary(std::fs::read(&file_name).unwrap())).unwrap(),
+        if handshake.is_some_and(|started| started.elapsed() > Duration::from_secs(10)) {
+            crashln!("The maid server did not answer the handshake, it is likely older than maid {VERSION} (protocol v{PROTOCOL_VERSION}). Please upgrade maid_server.");
+        }
+
+        if interrupted.load(Ordering::SeqCst) && !cancelling {
+            crate::log!(Level::Warning, "cancelling remote build, press ctrl-c again to quit");
+            cancelling = true;
+
+            if let Err(err) = socket.send(Message::Text(ClientMessage::Cancel.into())) {
+                log::warn!("Unable to cancel remote build: {err}");
+                return Session::Done(130);
+            }
+        }
+
+        let sent = match socket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str::<Response>(&text) {
+                Ok(Response { message, kind, level, code, protocol, offset, missing, .. }) => match kind {
+                    Kind::Hello => {
+                        if let Err(err) = maid_protocol::compatible("maid", "maid_server", message.as_deref().unwrap_or("unknown"), protocol.unwrap_or_default()) {
+                            crashln!("{err}");
+                        }
+
+                        handshake = None;
+                        log::debug!("sending information");
+                        socket.send(Message::Text(ClientMessage::task(connection_data.clone()).into())).map_err(Into::into)
+                    }
+                    Kind::Done => return Session::Done(code.unwrap_or(0) as i32),
+                    Kind::Message => {
+                        // the server only reports on the build once the whole archive arrived
+                        uploading = false;
+                        crate::log!(level, "{}", message.unwrap());
+                        Ok(())
                     }
+                    Kind::Binary => {
+                        uploading = true;
+                        socket.send(Message::Text(ClientMessage::Manifest { files: workspace.files.clone() }.into())).map_err(Into::into)
+                    }
+                    Kind::Missing => match missing.unwrap_or_default() {
+                        missing if missing.is_empty() => {
+                            crate::log!(Level::Notice, "workspace is up to date, nothing to upload");
+                            Ok(())
+                        }
+                        missing => {
+                            crate::log!(Level::Info, "uploading {} of {} files", missing.len(), workspace.files.len());
+
+                            match workspace.blobs(missing) {
+                                Ok(archive) => socket.send(Message::Text(ClientMessage::upload(&archive.id, archive.size).into())).map_err(Into::into),
+                                Err(err) => crashln!("Unable to create archive.\nError: {err}"),
+                            }
+                        }
+                    },
+                    Kind::Resume => match &workspace.blobs {
+                        Some(archive) => {
+                            let offset = offset.unwrap_or_default();
+                            then!(offset > 0, crate::log!(Level::Notice, "resuming upload at {}", HumanBytes(offset)));
+                            upload(socket, archive, offset, interrupted)
+                        }
+                        None => Ok(()),
+                    },
+                },
+                Err(err) => {
+                    log::debug!("unknown message: {err}");
+                    Ok(())
                 }
-            }
+            },
             Ok(Message::Binary(archive)) => {
                 let archive_name = match server::file::read_tar(&archive) {
                     Ok(name) => name,
@@ -148,25 +416,97 @@ pub fn remote(task: Task) {
                 }
 
                 server::file::remove_tar(&archive_name);
+                Ok(())
             }
-            Err(err) => {
-                crate::log!(Level::Fatal, "{err}");
-                break;
-            }
-            _ => (),
+            Err(Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(_) if cancelling => return Session::Done(130),
+            Err(err) => return dropped(err.into(), uploading),
+            _ => Ok(()),
         };
+
+        if let Err(err) = sent {
+            return dropped(err, uploading);
+        }
     }
+}
 
-    server::file::remove_tar(&file_name);
-    // run.rs:96 implement that later
-    println!("\n{} {}", helpers::string::check_icon(), "finished task successfully".bright_green());
-    println!("{}", "removed temporary archive".bright_magenta());
+fn request(path: &String, method: Method, route: &str) -> reqwest::blocking::Response {
+    let values = helpers::maidfile::merge(path);
+    let address = server::parse::address(&values);
+    let token = server::parse::token(&values);
 
-    if let Err(err) = socket.close(Some(CloseFrame {
-        code: Normal,
-        // run.rs:96 implement that later
-        reason: std::borrow::Cow::Borrowed("finished task successfully"),
-    })) {
-        crashln!("Unable to close socket.\nError: {err}")
+    let response = match Client::new().request(method, fmtstr!("{address}{route}")).header("Authorization", fmtstr!("Bearer {token}")).send() {
+        Ok(res) => res,
+        Err(err) => {
+            log::warn!("{err}");
+            crashln!("Unable to connect to the maid server. Is it up?");
+        }
+    };
+
+    match response.status().as_u16() {
+        200 | 202 => response,
+        401 | 403 => crashln!("The maid server rejected the request. Is the token correct?"),
+        404 => crashln!("No job found matching that id."),
+        409 => crashln!("That job is no longer running."),
+        code => crashln!("The maid server responded with status code {code}."),
+    }
+}
+
+pub fn jobs(path: &String, limit: usize) {
+    let jobs = match request(path, Method::GET, fmtstr!("/api/jobs?limit={limit}")).json::<Vec<Job>>() {
+        Ok(jobs) => jobs,
+        Err(err) => {
+            log::warn!("{err}");
+            crashln!("Unable to read the job history from the maid server.");
+        }
     };
+
+    if jobs.is_empty() {
+        return println!("{}", "no remote jobs".white());
+    }
+
+    for job in jobs {
+        let status = match job.status {
+            JobStatus::Finished => job.status.as_str().green(),
+            JobStatus::Failed => job.status.as_str().red(),
+            JobStatus::Cancelled => job.status.as_str().yellow(),
+            JobStatus::Running => job.status.as_str().bright_cyan(),
+        };
+
+        let code = job.code.map(|code| format!(" (code {code})")).unwrap_or_default();
+        let project = match job.project.is_empty() {
+            true => string!(),
+            false => format!("{}/", job.project),
+        };
+
+        println!(
+            "{} {}{} {} {}{} {}",
+            job.id[..12].bright_blue(),
+            project.white(),
+            job.task.bright_yellow(),
+            format!("[{}]", job.image).white(),
+            status,
+            code.white(),
+            cli::butler::ago(job.started.max(0) as u64).white()
+        );
+    }
+}
+
+pub fn logs(path: &String, id: &String) {
+    match request(path, Method::GET, fmtstr!("/api/jobs/{id}/log")).text() {
+        Ok(log) => print!("{log}"),
+        Err(err) => {
+            log::warn!("{err}");
+            crashln!("Unable to read the job log from the maid server.");
+        }
+    }
+}
+
+pub fn cancel(path: &String, id: &String) {
+    let message = match request(path, Method::DELETE, fmtstr!("/api/jobs/{id}")).status().as_u16() {
+        202 => "cancelling job",
+        _ => "cancelled job",
+    };
+
+    println!("{} {} {}", helpers::string::check_icon(), message.bright_green(), id.bright_blue());
 }
@@ -3,21 +3,11 @@ use crate::helpers;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use global_placeholders::global;
 use macros_rs::crashln;
-use std::{fs::write, fs::File, path::PathBuf};
-use tar::{Archive, Builder};
+use maid_protocol::FileEntry;
+use std::{collections::HashMap, fs::write, fs::File, time::UNIX_EPOCH};
+use tar::{Archive, Builder, Header};
 use uuid::Uuid;
 
-fn append_to_tar(builder: &mut Builder<GzEncoder<File>>, path: &String) -> Result<(), std::io::Error> {
-    let pathbuf = PathBuf::from(path);
-
-    if pathbuf.is_file() {
-        builder.append_path(&pathbuf)?;
-    } else if pathbuf.is_dir() {
-        builder.append_dir_all(&pathbuf, &pathbuf)?;
-    }
-    Ok(())
-}
-
 pub fn remove_tar(file: &String) {
     if let Err(_) = std::fs::remove_file(file) {
         crashln!("Unable to remove temporary archive. does it exist?");
@@ -44,7 +34,40 @@ pub fn unpack_tar(path: &String) -> std::io::Result<()> {
     archive.unpack(".")
 }
 
-pub fn write_tar(files: &Vec<String>) -> Result<String, std::io::Error> {
+/// blake3 hash and size of an archive, the server keys resumable uploads by the hash
+pub fn checksum(path: &String) -> Result<(String, u64), std::io::Error> { maid_protocol::hash(&mut File::open(path)?) }
+
+/// every file below the task's `push` paths, relative to the working directory, with its blake3 hash
+pub fn manifest(paths: &Vec<String>) -> Result<Vec<FileEntry>, std::io::Error> {
+    let root = std::env::current_dir()?;
+    let mut files = vec![];
+
+    for path in helpers::file::expand(&root, paths, false) {
+        let mut file = File::open(root.join(&path))?;
+        let metadata = file.metadata()?;
+        let (hash, size) = maid_protocol::hash(&mut file)?;
+
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777;
+        #[cfg(not(unix))]
+        let mode = 0o644;
+
+        files.push(FileEntry {
+            path: path.to_string_lossy().replace('\\', "/"),
+            modified: metadata.modified()?.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default(),
+            hash,
+            size,
+            mode,
+        });
+    }
+
+    Ok(files)
+}
+
+/// gzipped tar of the blobs the server is missing, each named by its hash
+///
+/// headers carry no timestamps or owners, so the same blobs always give the same archive and a dropped upload can resume
+pub fn write_blobs(files: &[FileEntry], missing: &[String]) -> Result<String, std::io::Error> {
     if !helpers::Exists::folder(global!("maid.temp_dir")).unwrap() {
         std::fs::create_dir_all(global!("maid.temp_dir")).unwrap();
         log::debug!("created maid temp dir");
@@ -52,14 +75,24 @@ pub fn write_tar(files: &Vec<String>) -> Result<String, std::io::Error> {
 
     let file_name = format!("{}/{}.tgz", global!("maid.temp_dir"), Uuid::new_v4());
     let archive = File::create(&file_name)?;
-    let enc = GzEncoder::new(archive, Compression::default());
-    let mut tar = Builder::new(enc);
+    let mut tar = Builder::new(GzEncoder::new(archive, Compression::default()));
+
+    let paths: HashMap<&str, &str> = files.iter().map(|file| (file.hash.as_str(), file.path.as_str())).collect();
 
     log::debug!("compressing to {}", &file_name);
-    for path in files {
-        append_to_tar(&mut tar, path)?;
-        log::debug!("{} {:?}", helpers::string::add_icon(), path);
+    for hash in missing {
+        let Some(path) = paths.get(hash.as_str()) else { continue };
+        let contents = File::open(path)?;
+        let mut header = Header::new_gnu();
+
+        header.set_size(contents.metadata()?.len());
+        header.set_mode(0o644);
+        header.set_cksum();
+
+        tar.append_data(&mut header, hash, contents)?;
+        log::debug!("{} {path} ({hash})", helpers::string::add_icon());
     }
 
+    tar.into_inner()?.finish()?;
     Ok(file_name)
 }
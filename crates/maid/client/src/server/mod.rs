@@ -1,4 +1,4 @@
-pub mod api;
+pub mod cache;
 pub mod cli;
 pub mod file;
 pub mod parse;
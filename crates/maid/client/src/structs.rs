@@ -1,79 +1,28 @@
+pub use maid_protocol::{Cache, ConnectionData, ConnectionInfo, Kind, Level, Maidfile, Param, ParamKind, Remote, Response, Tasks, Watch};
+
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::path::PathBuf;
 use toml::Value as TomlValue;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Maidfile {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub import: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub env: Option<BTreeMap<String, TomlValue>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub project: Option<Project>,
-    pub tasks: BTreeMap<String, Tasks>,
+pub struct CacheEntry {
+    pub task: String,
+    pub key: String,
+    pub created: u64,
+    pub accessed: u64,
+    pub size: u64,
+    pub files: Vec<CacheFile>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Project {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub name: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub version: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub server: Option<Server>, // wip
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Server {
-    pub address: Address, // wip
-    pub token: String,    // wip
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Address {
-    pub host: String,
-    pub port: i64,
-    pub ssl: bool,
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Tasks {
-    pub script: TomlValue,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub hide: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub path: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub info: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub cache: Option<Cache>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub remote: Option<Remote>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub depends: Option<Vec<String>>,
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Cache {
+pub struct CacheFile {
     pub path: String,
-    pub target: Vec<String>,
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct CacheConfig {
-    pub target: Vec<String>,
     pub hash: String,
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Remote {
-    pub push: Vec<String>,
-    pub pull: String,
-    pub image: String,
-    pub shell: String,
-    pub silent: bool,
-    pub exclusive: bool,
+    pub size: u64,
+    pub mtime: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -86,6 +35,7 @@ pub struct Task {
     pub script: TomlValue,
     pub path: String,
     pub args: Vec<String>,
+    pub vars: BTreeMap<String, String>,
     pub silent: bool,
     pub is_dep: bool,
 }
@@ -97,57 +47,50 @@ pub struct Runner<'a> {
     pub script: Vec<&'a str>,
     pub path: &'a String,
     pub args: &'a Vec<String>,
+    pub vars: &'a BTreeMap<String, String>,
     pub project: &'a PathBuf,
+    pub shell: Option<String>,
     pub silent: bool,
     pub is_dep: bool,
+    pub continue_on_error: bool,
 }
 
-#[derive(Debug)]
-pub struct DisplayTask {
-    pub name: String,
-    pub formatted: String,
-    pub hidden: bool,
+#[derive(Clone, Debug)]
+pub struct Combination {
+    pub label: String,
+    pub vars: BTreeMap<String, String>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
-pub enum Level {
-    None,
-    Fatal,
-    Docker,
-    Debug,
-    Error,
-    Notice,
-    Info,
-    Build,
-    Warning,
-    Success,
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub name: String,
+    pub code: i32,
+    pub line: Option<(usize, String)>,
+    pub output: Option<String>,
+    pub skipped: Option<&'static str>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub enum Kind {
-    Done,
-    Binary,
-    Message,
-}
+impl Outcome {
+    pub fn ok(name: &str) -> Outcome {
+        Outcome {
+            name: name.to_string(),
+            code: 0,
+            line: None,
+            output: None,
+            skipped: None,
+        }
+    }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Websocket {
-    pub level: Level,
-    pub kind: Kind,
-    pub time: i64,
-    pub message: Option<String>,
-}
+    /// `line` is the failing script line and its number, when the task stopped at one
+    pub fn failed(name: &str, code: i32, line: Option<(usize, String)>) -> Outcome { Outcome { code, line, ..Outcome::ok(name) } }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct ConnectionInfo {
-    pub name: String,
-    pub remote: Remote,
-    pub args: Vec<String>,
-    pub script: Vec<String>,
+    /// a task that did not run, `reason` is shown next to it in the dependency summary
+    pub fn skipped(name: &str, reason: &'static str) -> Outcome { Outcome { skipped: Some(reason), ..Outcome::ok(name) } }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct ConnectionData {
-    pub info: ConnectionInfo,
-    pub maidfile: Maidfile,
+#[derive(Debug)]
+pub struct DisplayTask {
+    pub name: String,
+    pub formatted: String,
+    pub hidden: bool,
 }
@@ -1,11 +1,344 @@
-use macros_rs::crashln;
-use merkle_hash::{bytes_to_hex, Algorithm, MerkleTree};
+use crate::helpers;
+use crate::structs::{Cache, CacheEntry, CacheFile, Maidfile};
 
-pub fn create_hash(path: &str) -> String {
-    let tree = match MerkleTree::builder(path).algorithm(Algorithm::Blake3).hash_names(false).build() {
-        Ok(v) => v,
-        Err(e) => crashln!("Invalid UTF-8 sequence: {}", e),
+use colored::Colorize;
+use filetime::FileTime;
+use global_placeholders::global;
+use human_bytes::human_bytes;
+use macros_rs::{crashln, string};
+use std::{collections::BTreeMap, collections::HashMap, fs::File, path::Path, path::PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use text_placeholder::Template;
+use toml::Value;
+use uuid::Uuid;
+
+/// every path and glob the cache key depends on, including the legacy `cache.path`
+pub fn inputs(cache: &Cache) -> Vec<String> {
+    let mut inputs: Vec<String> = cache.path.iter().filter(|path| !path.trim().is_empty()).cloned().collect();
+    inputs.extend(cache.inputs.clone().unwrap_or_default());
+    return inputs;
+}
+
+pub fn enabled(cache: &Cache) -> bool { !cache.target.is_empty() && !inputs(cache).is_empty() }
+
+/// hashes the maid version, task definition, resolved script, variables and the names and contents of every input file
+pub fn create_hash(task: &str, values: &Maidfile, table: &HashMap<&str, &str>, inputs: &Vec<String>, root: &Path) -> String {
+    let mut hasher = blake3::Hasher::new();
+    let definition = &values.tasks[task];
+
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(task.as_bytes());
+    hasher.update(serde_json::to_string(definition).unwrap_or_default().as_bytes());
+
+    let script: Vec<&str> = match &definition.script {
+        Value::Array(lines) => lines.iter().filter_map(|line| line.as_str()).collect(),
+        script => script.as_str().into_iter().collect(),
+    };
+
+    for line in script {
+        hasher.update(Template::new_with_placeholder(line, "%{", "}").fill_with_hashmap(table).as_bytes());
+        hasher.update(b"\0");
+    }
+
+    // args only matter where the script uses them, and those lines are already hashed filled in, dependencies
+    // inherit the root task's args, so hashing them would give a dependency a new key under every root task
+    let vars: BTreeMap<&&str, &&str> = table.iter().filter(|(key, _)| !key.starts_with("dir.") && !key.starts_with("arg.")).collect();
+    for (key, value) in vars {
+        hasher.update(format!("{key}={value}\0").as_bytes());
+    }
+
+    for file in helpers::file::expand(root, inputs, true) {
+        log::trace!("hashing cache input {:?}", file);
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+
+        match File::open(root.join(&file)) {
+            Ok(mut contents) => {
+                if let Err(err) = std::io::copy(&mut contents, &mut hasher) {
+                    crashln!("Cannot hash cache input {:?}.\n{err}", file);
+                }
+            }
+            Err(err) => crashln!("Cannot read cache input {:?}.\n{err}", file),
+        };
+    }
+
+    hasher.finalize().to_hex().to_string()
+}
+
+fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default() }
+
+fn entry_path(task: &str, key: &str) -> PathBuf { Path::new(&global!("maid.cache_entries", task)).join(format!("{key}.toml")) }
+
+pub fn blob_path(hash: &str) -> PathBuf { Path::new(&global!("maid.cache_objects")).join(&hash[..2]).join(hash) }
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let contents = std::fs::read_to_string(path).ok()?;
+
+    match toml::from_str::<CacheEntry>(&contents) {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            log::warn!("Cannot read cache entry {:?}: {err}", path);
+            None
+        }
+    }
+}
+
+fn write_entry(entry: &CacheEntry) {
+    let path = entry_path(&entry.task, &entry.key);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+
+    match std::fs::write(&path, toml::to_string(entry).unwrap()) {
+        Ok(_) => log::debug!("added cache entry for {} -> {}", entry.task, entry.key),
+        Err(err) => crashln!("error {err} creating cache entry"),
+    };
+}
+
+/// every entry in the local store, across all tasks
+pub fn entries() -> Vec<CacheEntry> {
+    let root = global!("maid.cache_entries", "");
+    let mut entries: Vec<CacheEntry> = vec![];
+
+    for dir in std::fs::read_dir(root).into_iter().flatten().flatten() {
+        for file in std::fs::read_dir(dir.path()).into_iter().flatten().flatten() {
+            entries.extend(read_entry(&file.path()));
+        }
+    }
+
+    return entries;
+}
+
+/// blobs on disk with their sizes, keyed by hash
+pub fn blobs() -> BTreeMap<String, u64> {
+    helpers::file::expand(Path::new(&global!("maid.cache_objects")), &vec![string!(".")], false)
+        .into_iter()
+        .filter_map(|path| {
+            let size = std::fs::metadata(blob_path(&path.file_name()?.to_string_lossy())).ok()?.len();
+            Some((path.file_name()?.to_string_lossy().to_string(), size))
+        })
+        .collect()
+}
+
+/// finds a stored entry for this key, marking it as recently used
+pub fn lookup(task: &str, key: &str) -> Option<CacheEntry> {
+    let mut entry = read_entry(&entry_path(task, key))?;
+
+    if let Some(file) = entry.files.iter().find(|file| !blob_path(&file.hash).exists()) {
+        log::debug!("cache entry {key} is missing the blob for {}", file.path);
+        return None;
+    }
+
+    entry.accessed = now();
+    write_entry(&entry);
+
+    return Some(entry);
+}
+
+/// accepts plain byte counts or sizes such as "500MB" and "2 GiB"
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|char: char| !char.is_ascii_digit() && char != '.').unwrap_or(value.len());
+    let number = value[..split].parse::<f64>().ok()?;
+
+    let unit: u64 = match value[split..].trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1000,
+        "mb" | "m" => 1000_u64.pow(2),
+        "gb" | "g" => 1000_u64.pow(3),
+        "tb" | "t" => 1000_u64.pow(4),
+        "kib" => 1024,
+        "mib" => 1024_u64.pow(2),
+        "gib" => 1024_u64.pow(3),
+        "tib" => 1024_u64.pow(4),
+        _ => return None,
+    };
+
+    Some((number * unit as f64) as u64)
+}
+
+/// size limit of the local store from `[project.cache] limit`
+pub fn limit(values: &Maidfile) -> Option<u64> {
+    let limit = values.project.as_ref()?.cache.as_ref()?.limit.as_ref()?;
+
+    let parsed = match limit {
+        Value::Integer(bytes) => u64::try_from(*bytes).ok(),
+        Value::String(size) => parse_size(size),
+        _ => None,
     };
 
-    bytes_to_hex(tree.root.item.hash)
+    match parsed {
+        Some(bytes) => Some(bytes),
+        None => crashln!("Invalid cache limit '{limit}', expected a size like \"500MB\"."),
+    }
+}
+
+fn store_blob(path: &Path) -> std::io::Result<(String, u64)> {
+    let mut hasher = blake3::Hasher::new();
+    let size = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    let hash = hasher.finalize().to_hex().to_string();
+    let blob = blob_path(&hash);
+
+    if !blob.exists() {
+        let temp = blob.with_extension(format!("{}.part", Uuid::new_v4().simple()));
+
+        std::fs::create_dir_all(blob.parent().unwrap())?;
+        std::fs::copy(path, &temp)?;
+        std::fs::rename(&temp, &blob)?;
+        log::trace!("stored blob {hash}");
+    }
+
+    Ok((hash, size))
+}
+
+/// copies a blob into place, restoring the recorded permissions and modification time
+fn restore_file(file: &CacheFile, to: &Path) -> std::io::Result<u64> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if to.exists() {
+        std::fs::remove_file(to)?;
+    }
+
+    let size = std::fs::copy(blob_path(&file.hash), to)?;
+    filetime::set_file_mtime(to, FileTime::from_unix_time(file.mtime, 0))?;
+
+    #[cfg(unix)]
+    if let Some(mode) = file.mode {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(to, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(size)
+}
+
+/// files of an entry belonging to a target, which may be a file, a directory or a glob
+fn matches(target: &str, file: &str) -> bool {
+    match helpers::file::is_glob(target) {
+        true => helpers::file::glob_set(&vec![string!(target)]).is_match(file),
+        false => Path::new(file).starts_with(target.trim_start_matches("./")),
+    }
+}
+
+/// stores every target as deduplicated blobs under a new entry for this key, reporting the size of each target
+fn store<F: FnMut(&str, u64)>(task: &str, key: &str, cache: &Cache, root: &Path, mut report: F) -> CacheEntry {
+    let mut files: Vec<CacheFile> = vec![];
+
+    for target in cache.target.iter() {
+        let mut size: u64 = 0;
+
+        for file in helpers::file::expand(root, &vec![target.clone()], false) {
+            let path = root.join(&file);
+
+            let (hash, bytes) = match store_blob(&path) {
+                Ok(blob) => blob,
+                Err(err) => {
+                    log::warn!("{err}");
+                    log::debug!("path: {:?}", file);
+                    crashln!("Cannot save target file.");
+                }
+            };
+
+            let metadata = std::fs::metadata(&path).ok();
+
+            #[cfg(unix)]
+            let mode = metadata.as_ref().map(|metadata| std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()));
+            #[cfg(not(unix))]
+            let mode = None;
+
+            size += bytes;
+            files.push(CacheFile {
+                path: file.to_string_lossy().replace('\\', "/"),
+                hash,
+                size: bytes,
+                mtime: metadata.as_ref().map(|metadata| FileTime::from_last_modification_time(metadata).unix_seconds()).unwrap_or_default(),
+                mode,
+            });
+        }
+
+        report(target, size);
+    }
+
+    let entry = CacheEntry {
+        task: string!(task),
+        key: string!(key),
+        created: now(),
+        accessed: now(),
+        size: files.iter().map(|file| file.size).sum(),
+        files,
+    };
+
+    write_entry(&entry);
+    return entry;
+}
+
+pub fn save(task: &str, key: &str, cache: &Cache, root: &Path, silent: bool) -> CacheEntry {
+    store(task, key, cache, root, |target, size| match silent {
+        true => println!("{} {}{}{}", helpers::string::add_icon(), target.bright_green(), helpers::string::seperator(), human_bytes(size as f64).bright_cyan()),
+        false => println!("{} ({})", format!("saved target '{target}' to cache").bright_magenta(), human_bytes(size as f64).white()),
+    })
+}
+
+/// adds files unpacked from elsewhere, such as the remote cache, to the local store
+pub fn import(task: &str, key: &str, cache: &Cache, root: &Path) -> CacheEntry { store(task, key, cache, root, |target, size| log::debug!("imported target {target} ({size} bytes)")) }
+
+/// copies every file of a cached entry back into place
+pub fn restore(entry: &CacheEntry, cache: &Cache, root: &Path, silent: bool) {
+    if !silent {
+        println!("{}", "skipping task due to cached files".bright_magenta());
+    }
+
+    for target in cache.target.iter() {
+        let mut size: u64 = 0;
+
+        for file in entry.files.iter().filter(|file| matches(target, &file.path)) {
+            match restore_file(file, &root.join(&file.path)) {
+                Ok(bytes) => {
+                    size += bytes;
+                    log::debug!("copied target file {}", file.path);
+                }
+                Err(err) => {
+                    log::warn!("{err}");
+                    crashln!("Cannot copy target file.");
+                }
+            };
+        }
+
+        if !silent {
+            println!("{} ({})", format!("copied target '{target}' from cache").magenta(), human_bytes(size as f64).white());
+        }
+    }
+}
+
+/// evicts least recently used entries until the stored blobs fit the limit, then removes unreferenced blobs
+pub fn prune(limit: Option<u64>) -> (usize, u64) {
+    let mut entries = entries();
+    let mut kept: BTreeMap<String, u64> = BTreeMap::new();
+    let mut removed = 0;
+
+    entries.sort_by_key(|entry| std::cmp::Reverse((entry.accessed, entry.created)));
+
+    for entry in entries {
+        let mut next = kept.clone();
+        next.extend(entry.files.iter().map(|file| (file.hash.clone(), file.size)));
+
+        if limit.is_some_and(|limit| next.values().sum::<u64>() > limit) {
+            log::debug!("evicting cache entry {} for {}", entry.key, entry.task);
+            std::fs::remove_file(entry_path(&entry.task, &entry.key)).ok();
+            removed += 1;
+        } else {
+            kept = next;
+        }
+    }
+
+    let mut freed: u64 = 0;
+    for (hash, size) in blobs().into_iter().filter(|(hash, _)| !kept.contains_key(hash)) {
+        if std::fs::remove_file(blob_path(&hash)).is_ok() {
+            freed += size;
+        }
+    }
+
+    return (removed, freed);
 }
@@ -0,0 +1,97 @@
+use crate::structs::Maidfile;
+
+use macros_rs::crashln;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+enum Visit {
+    Active,
+    Done,
+}
+
+#[derive(Clone, Debug)]
+pub struct Graph {
+    pub root: String,
+    pub nodes: BTreeMap<String, Vec<String>>,
+}
+
+impl Graph {
+    pub fn build(maidfile: &Maidfile, root: &str) -> Graph {
+        let mut graph = Graph {
+            root: root.to_string(),
+            nodes: BTreeMap::new(),
+        };
+
+        let mut states: BTreeMap<String, Visit> = BTreeMap::new();
+        let mut stack: Vec<String> = vec![];
+
+        graph.visit(maidfile, root, &mut states, &mut stack);
+        log::debug!("Dependency graph: {:?}", graph.nodes);
+
+        return graph;
+    }
+
+    fn visit(&mut self, maidfile: &Maidfile, name: &str, states: &mut BTreeMap<String, Visit>, stack: &mut Vec<String>) {
+        match states.get(name) {
+            Some(Visit::Done) => return,
+            Some(Visit::Active) => {
+                let start = stack.iter().position(|item| item == name).unwrap_or_default();
+                let mut cycle = stack[start..].to_vec();
+
+                cycle.push(name.to_string());
+                crashln!("Dependency cycle detected: {}", cycle.join(" -> "));
+            }
+            None => {}
+        }
+
+        let mut deps = match maidfile.tasks.get(name) {
+            Some(task) => task.depends.clone().unwrap_or_default(),
+            None => match stack.last() {
+                Some(parent) => crashln!("Maid could not find the task '{name}' required by '{parent}'. Does it exist?"),
+                None => crashln!("Maid could not find the task '{name}'. Does it exist?"),
+            },
+        };
+
+        let mut seen = BTreeSet::new();
+        deps.retain(|dep| seen.insert(dep.clone()));
+
+        states.insert(name.to_string(), Visit::Active);
+        stack.push(name.to_string());
+
+        for dep in deps.iter() {
+            self.visit(maidfile, dep, states, stack);
+        }
+
+        stack.pop();
+        states.insert(name.to_string(), Visit::Done);
+        self.nodes.insert(name.to_string(), deps);
+    }
+
+    pub fn dependents(&self, name: &str) -> Vec<&String> { self.nodes.iter().filter(|(_, deps)| deps.iter().any(|dep| dep == name)).map(|(key, _)| key).collect() }
+
+    /// topological order of every dependency, excluding the root task
+    pub fn order(&self) -> Vec<String> {
+        let mut pending: BTreeMap<&String, usize> = self.nodes.iter().map(|(name, deps)| (name, deps.len())).collect();
+        let mut ready: VecDeque<&String> = pending.iter().filter(|(_, count)| **count == 0).map(|(name, _)| *name).collect();
+        let mut order: Vec<String> = vec![];
+
+        while let Some(name) = ready.pop_front() {
+            order.push(name.clone());
+
+            for dependent in self.dependents(name) {
+                if let Some(count) = pending.get_mut(dependent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+        }
+
+        if order.len() != self.nodes.len() {
+            crashln!("Unable to order the dependencies of '{}'.", self.root);
+        }
+
+        order.retain(|name| name != &self.root);
+        return order;
+    }
+}
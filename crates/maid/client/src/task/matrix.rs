@@ -0,0 +1,78 @@
+use crate::structs::Combination;
+
+use indexmap::IndexMap;
+use macros_rs::string;
+use std::{borrow::Cow, collections::BTreeMap};
+use text_placeholder::Template;
+use toml::Value;
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Table(table) => match table.get("name") {
+            Some(name) => value_to_string(name),
+            None => table.values().map(value_to_string).filter(|value| !value.is_empty()).collect::<Vec<_>>().join("/"),
+        },
+        value => value.to_string(),
+    }
+}
+
+/// every combination of the matrix values, keys and their lists both in declaration order
+pub fn expand(matrix: &IndexMap<String, Vec<Value>>) -> Vec<Combination> {
+    let mut combinations = vec![Combination { label: string!(), vars: BTreeMap::new() }];
+
+    for (key, values) in matrix {
+        let mut expanded: Vec<Combination> = vec![];
+
+        for combination in combinations.iter() {
+            for value in values {
+                let mut next = combination.clone();
+                let label = format!("{key}={}", value_to_string(value));
+
+                next.vars.insert(format!("matrix.{key}"), value_to_string(value));
+                if let Value::Table(table) = value {
+                    for (field, item) in table {
+                        next.vars.insert(format!("matrix.{key}.{field}"), value_to_string(item));
+                    }
+                }
+
+                next.label = match next.label.is_empty() {
+                    true => label,
+                    false => format!("{}, {label}", next.label),
+                };
+
+                expanded.push(next);
+            }
+        }
+
+        combinations = expanded;
+    }
+
+    log::debug!("Expanded matrix into {} combinations", combinations.len());
+    return combinations;
+}
+
+/// replaces matrix placeholders only, leaving every other placeholder for later
+pub fn fill(script: &str, vars: &BTreeMap<String, String>) -> String {
+    let result = Template::new_with_placeholder(script, "%{", "}").fill_with_function(|key| match vars.get(key) {
+        Some(value) => Some(Cow::Owned(value.clone())),
+        None => Some(Cow::Owned(format!("%{{{key}}}"))),
+    });
+
+    result.unwrap_or_else(|_| string!(script))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maid_protocol::maidfile::Maidfile;
+
+    #[test]
+    fn expand_keeps_the_declared_key_order() {
+        let maidfile: Maidfile = toml::from_str("[tasks.build]\nscript = 'true'\nmatrix = { target = ['linux', 'darwin'], profile = ['release'] }").unwrap();
+        let combinations = expand(maidfile.tasks["build"].matrix.as_ref().unwrap());
+        let labels: Vec<&str> = combinations.iter().map(|combination| combination.label.as_str()).collect();
+
+        assert_eq!(labels, ["target=linux, profile=release", "target=darwin, profile=release"]);
+    }
+}
@@ -1,2 +1,6 @@
 pub mod cache;
+pub mod graph;
+pub mod matrix;
 pub mod progress;
+pub mod scheduler;
+pub mod sources;
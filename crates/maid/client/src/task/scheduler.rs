@@ -0,0 +1,100 @@
+use crate::structs::Outcome;
+use crate::task::{self, graph::Graph};
+
+use colored::Colorize;
+use indicatif::MultiProgress;
+use macros_rs::fmtstr;
+use std::{collections::BTreeMap, collections::BTreeSet, collections::VecDeque, sync::mpsc, thread, time::Instant};
+
+pub fn jobs(jobs: Option<usize>) -> usize {
+    match jobs {
+        Some(jobs) => jobs.max(1),
+        None => thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+    }
+}
+
+/// runs every dependency of the graph root, returning the outcome of each finished dependency
+pub fn run<F>(graph: &Graph, jobs: usize, exec: F) -> Result<Vec<Outcome>, Outcome>
+where
+    F: Fn(&str) -> Outcome + Sync,
+{
+    let order = graph.order();
+    let multi = MultiProgress::new();
+    let ticks = vec!["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+    let template = fmtstr!("{{prefix:.white}} {{spinner:.yellow}}{{msg}} {}", "({elapsed})".bright_cyan());
+
+    let mut pending: BTreeMap<&String, usize> = order.iter().map(|name| (name, graph.nodes[name].len())).collect();
+    let mut ready: VecDeque<&String> = order.iter().filter(|name| pending[name] == 0).collect();
+    let mut started: BTreeSet<&String> = BTreeSet::new();
+    let mut failed: Option<Outcome> = None;
+    let mut finished: Vec<Outcome> = vec![];
+    let mut running = 0;
+
+    log::debug!("Running {} dependencies with {jobs} jobs", order.len());
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel::<(&String, Outcome)>();
+
+        loop {
+            while running < jobs && failed.is_none() {
+                let name = match ready.pop_front() {
+                    Some(name) => name,
+                    None => break,
+                };
+
+                if !started.insert(name) {
+                    log::debug!("Skipping dependency: {name} (already ran)");
+                    continue;
+                }
+
+                let tx = tx.clone();
+                let exec = &exec;
+                let index = order.iter().position(|item| item == name).unwrap_or_default();
+                let pb = multi.add(task::progress::init(ticks.clone(), template, 80));
+
+                pb.set_prefix(format!("[{}/{}]", index + 1, order.len()));
+                pb.set_message(fmtstr!("{} {name}", "running dependency".bright_yellow()));
+
+                running += 1;
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let outcome = exec(name);
+
+                    log::debug!("Finished dependency: {name} with exit code: {} in {:.2?}", outcome.code, start.elapsed());
+                    pb.finish_and_clear();
+                    tx.send((name, outcome)).ok();
+                });
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let (name, outcome) = match rx.recv() {
+                Ok(result) => result,
+                Err(_) => break,
+            };
+
+            running -= 1;
+            if outcome.code != 0 {
+                failed.get_or_insert(outcome);
+                continue;
+            }
+
+            finished.push(outcome);
+            for dependent in graph.dependents(name) {
+                if let Some(count) = pending.get_mut(dependent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+        }
+    });
+
+    match failed {
+        Some(outcome) => Err(outcome),
+        None => Ok(finished),
+    }
+}
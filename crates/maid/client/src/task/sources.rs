@@ -0,0 +1,80 @@
+use crate::helpers;
+use crate::structs::Maidfile;
+
+use global_placeholders::global;
+use std::{fs::File, path::Path, path::PathBuf, time::SystemTime};
+
+fn checksum_path(task: &str) -> PathBuf { Path::new(&global!("maid.cache_dir")).join("sources").join(format!("{task}.hash")) }
+
+fn modified(root: &Path, files: &[PathBuf]) -> Vec<SystemTime> { files.iter().filter_map(|file| std::fs::metadata(root.join(file)).and_then(|metadata| metadata.modified()).ok()).collect() }
+
+/// true when the task declares both `sources` and `generates`
+pub fn enabled(values: &Maidfile, task: &str) -> bool {
+    let definition = &values.tasks[task];
+    definition.sources.as_ref().is_some_and(|sources| !sources.is_empty()) && definition.generates.as_ref().is_some_and(|generates| !generates.is_empty())
+}
+
+/// hash of the task definition and the names and contents of every source file
+fn checksum(values: &Maidfile, task: &str, root: &Path) -> String {
+    let definition = &values.tasks[task];
+    let mut hasher = blake3::Hasher::new();
+
+    hasher.update(serde_json::to_string(definition).unwrap_or_default().as_bytes());
+
+    for file in helpers::file::expand(root, &definition.sources.clone().unwrap_or_default(), true) {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+
+        if let Ok(mut contents) = File::open(root.join(&file)) {
+            std::io::copy(&mut contents, &mut hasher).ok();
+        }
+    }
+
+    hasher.finalize().to_hex().to_string()
+}
+
+/// every output exists and is newer than every source, or the sources match the last successful run
+pub fn up_to_date(values: &Maidfile, task: &str, root: &Path) -> bool {
+    let definition = &values.tasks[task];
+    let generates = definition.generates.clone().unwrap_or_default();
+    let outputs = helpers::file::expand(root, &generates, false);
+
+    let missing = generates.iter().any(|pattern| match helpers::file::is_glob(pattern) {
+        true => outputs.is_empty(),
+        false => !root.join(pattern).exists(),
+    });
+
+    if missing {
+        log::debug!("{task} is missing generated files");
+        return false;
+    }
+
+    let sources = helpers::file::expand(root, &definition.sources.clone().unwrap_or_default(), true);
+    let newest_source = modified(root, &sources).into_iter().max();
+    let oldest_output = modified(root, &outputs).into_iter().min();
+
+    if let (Some(source), Some(output)) = (newest_source, oldest_output) {
+        if output >= source {
+            log::debug!("{task} outputs are newer than its sources");
+            return true;
+        }
+    }
+
+    match std::fs::read_to_string(checksum_path(task)) {
+        Ok(previous) => previous.trim() == checksum(values, task, root),
+        Err(_) => false,
+    }
+}
+
+/// remembers the source checksum after a successful run
+pub fn record(values: &Maidfile, task: &str, root: &Path) {
+    let path = checksum_path(task);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).ok();
+    }
+
+    if let Err(err) = std::fs::write(&path, checksum(values, task, root)) {
+        log::warn!("unable to record sources of {task}: {err}");
+    }
+}
@@ -0,0 +1,40 @@
+/// bytes of an uploaded archive carried by one binary frame
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+const HEADER_SIZE: usize = 8 + blake3::OUT_LEN;
+
+/// one piece of an upload, framed as the offset (u64, big endian), the blake3 hash of the data and the data itself
+pub struct Chunk<'a> {
+    pub offset: u64,
+    pub data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    pub fn new(offset: u64, data: &'a [u8]) -> Chunk<'a> { Chunk { offset, data } }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE + self.data.len());
+
+        frame.extend_from_slice(&self.offset.to_be_bytes());
+        frame.extend_from_slice(blake3::hash(self.data).as_bytes());
+        frame.extend_from_slice(self.data);
+
+        frame
+    }
+
+    /// parses a binary frame, rejecting it when the data does not match its checksum
+    pub fn decode(frame: &'a [u8]) -> Result<Chunk<'a>, String> {
+        if frame.len() < HEADER_SIZE {
+            return Err(format!("chunk of {} bytes is shorter than its header", frame.len()));
+        }
+
+        let (header, data) = frame.split_at(HEADER_SIZE);
+        let offset = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let checksum = blake3::Hash::from_bytes(header[8..].try_into().unwrap());
+
+        match blake3::hash(data) == checksum {
+            true => Ok(Chunk { offset, data }),
+            false => Err(format!("checksum mismatch for chunk at offset {offset}")),
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+/// a reported value with the color the client prints it in
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Value<T> {
     pub data: T,
     pub hue: String,
 }
 
+/// the response of `/api/health`
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Route {
     pub platform: Value<String>,
@@ -20,3 +22,7 @@ pub struct Status {
     pub healthy: Value<String>,
     pub containers: Value<Vec<String>>,
 }
+
+impl<T> Value<T> {
+    pub fn new(data: T, hue: &str) -> Value<T> { Value { data, hue: hue.to_string() } }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+/// a remote build as recorded by the server and listed through `/api/jobs`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub task: String,
+    pub image: String,
+    pub project: String,
+    pub token: String,
+    pub status: JobStatus,
+    pub started: i64,
+    pub ended: Option<i64>,
+    pub code: Option<i64>,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Finished => "finished",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
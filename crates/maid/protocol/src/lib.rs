@@ -0,0 +1,20 @@
+//! Types shared by the `maid` client and `maid_server`: the maidfile, the gateway messages, the api responses, workspace manifests, upload chunks and the placeholder table.
+
+pub mod chunk;
+pub mod health;
+pub mod jobs;
+pub mod maidfile;
+pub mod manifest;
+pub mod message;
+pub mod table;
+
+pub use chunk::*;
+pub use maidfile::*;
+pub use manifest::*;
+pub use message::*;
+
+/// version of the crates, reported during the handshake
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// bumped whenever the gateway messages change incompatibly
+pub const PROTOCOL_VERSION: u32 = 4;
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+
+/// one file of a task's `push` paths, its contents are stored on the server as a blob named by `hash`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub hash: String,
+    pub size: u64,
+    pub mode: u32,
+    pub modified: u64,
+}
+
+/// blake3 hash and length of everything read from `reader`
+pub fn hash(reader: &mut impl Read) -> io::Result<(String, u64)> {
+    let mut hasher = blake3::Hasher::new();
+    let size = io::copy(reader, &mut hasher)?;
+
+    Ok((hasher.finalize().to_hex().to_string(), size))
+}
+
+/// whether a value looks like a hex encoded blake3 hash, which is safe to use as a file name
+pub fn is_hash(value: &str) -> bool { value.len() == 2 * blake3::OUT_LEN && value.chars().all(|char| char.is_ascii_hexdigit()) }
@@ -0,0 +1,165 @@
+use crate::maidfile::{Maidfile, Remote};
+use crate::manifest::FileEntry;
+use crate::{PROTOCOL_VERSION, VERSION};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum Level {
+    None,
+    Fatal,
+    Docker,
+    Debug,
+    Error,
+    Notice,
+    Info,
+    Build,
+    Warning,
+    Success,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Kind {
+    Done,
+    Hello,
+    Binary,
+    Missing,
+    Resume,
+    Message,
+}
+
+/// every text frame the server sends over the gateway
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Response {
+    pub level: Level,
+    pub kind: Kind,
+    pub time: i64,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing: Option<Vec<String>>,
+}
+
+impl Response {
+    pub fn new(level: Level, kind: Kind, message: Option<String>, code: Option<i64>) -> Response {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as i64).unwrap_or_default();
+        Response {
+            level,
+            kind,
+            time,
+            message,
+            code,
+            protocol: None,
+            offset: None,
+            missing: None,
+        }
+    }
+
+    pub fn message(level: Level, message: impl Into<String>) -> Response { Response::new(level, Kind::Message, Some(message.into()), None) }
+
+    pub fn binary() -> Response { Response::new(Level::Success, Kind::Binary, None, None) }
+
+    pub fn done(code: i64) -> Response {
+        let level = match code {
+            0 => Level::Success,
+            _ => Level::Error,
+        };
+
+        Response::new(level, Kind::Done, None, Some(code))
+    }
+
+    /// the blobs of a manifest the server does not have yet, an empty list means nothing has to be uploaded
+    pub fn missing(hashes: Vec<String>) -> Response {
+        Response {
+            missing: Some(hashes),
+            ..Response::new(Level::Info, Kind::Missing, None, None)
+        }
+    }
+
+    /// asks the client to stream the blob archive starting at `offset`
+    pub fn resume(offset: u64) -> Response {
+        Response {
+            offset: Some(offset),
+            ..Response::new(Level::Info, Kind::Resume, None, None)
+        }
+    }
+
+    /// the server's answer to a compatible [`ClientMessage::Hello`]
+    pub fn hello() -> Response {
+        Response {
+            protocol: Some(PROTOCOL_VERSION),
+            ..Response::new(Level::Success, Kind::Hello, Some(VERSION.to_string()), None)
+        }
+    }
+}
+
+impl From<Response> for String {
+    fn from(response: Response) -> Self { serde_json::to_string(&response).unwrap() }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConnectionInfo {
+    pub name: String,
+    pub remote: Remote,
+    pub args: Vec<String>,
+    pub script: Vec<String>,
+    /// resolved `param.*` values, filled into the script on the server
+    pub vars: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConnectionData {
+    pub info: ConnectionInfo,
+    pub maidfile: Maidfile,
+}
+
+/// every text frame the client sends over the gateway, the missing blobs travel as binary [`Chunk`](crate::Chunk) frames
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientMessage {
+    Hello { version: String, protocol: u32 },
+    Task(Box<ConnectionData>),
+    /// lists the workspace files, the server answers with the blobs it is missing
+    Manifest { files: Vec<FileEntry> },
+    /// announces the archive of missing blobs by its blake3 hash, so an interrupted upload can be resumed
+    Upload { id: String, size: u64 },
+    Cancel,
+}
+
+impl ClientMessage {
+    pub fn hello() -> ClientMessage {
+        ClientMessage::Hello {
+            version: VERSION.to_string(),
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+
+    pub fn task(data: ConnectionData) -> ClientMessage { ClientMessage::Task(Box::new(data)) }
+
+    pub fn upload(id: &str, size: u64) -> ClientMessage { ClientMessage::Upload { id: id.to_string(), size } }
+}
+
+impl From<ClientMessage> for String {
+    fn from(message: ClientMessage) -> Self { serde_json::to_string(&message).unwrap() }
+}
+
+/// checks a peer's protocol version, naming the side that has to be upgraded when they differ
+pub fn compatible(local: &str, peer: &str, version: &str, protocol: u32) -> Result<(), String> {
+    if protocol == PROTOCOL_VERSION {
+        return Ok(());
+    }
+
+    let outdated = match protocol < PROTOCOL_VERSION {
+        true => peer,
+        false => local,
+    };
+
+    Err(format!("{peer} {version} speaks protocol v{protocol} but {local} {VERSION} speaks v{PROTOCOL_VERSION}, please upgrade {outdated}"))
+}
@@ -0,0 +1,143 @@
+use crate::maidfile::Maidfile;
+
+use colored::{ColoredString, Colorize};
+use macros_rs::{crashln, errorln, str, ternary};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::{collections::BTreeMap, collections::HashMap, env};
+use text_placeholder::Template;
+use toml::Value;
+
+fn add_icon() -> ColoredString { "+".green() }
+
+fn path_to_str(path: &Path) -> &'static str { Box::leak(String::from(path.to_string_lossy()).into_boxed_str()) }
+
+fn trim_start_end(value: &str) -> &str {
+    let mut chars = value.chars();
+    chars.next();
+    chars.next_back();
+    chars.as_str()
+}
+
+fn env_files(value: &Option<Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(path)) => vec![path.clone()],
+        Some(Value::Array(paths)) => paths.iter().filter_map(|path| path.as_str().map(String::from)).collect(),
+        Some(value) => {
+            errorln!("Unable to parse maidfile. Contains unexpected {} values.", value.type_str());
+            vec![]
+        }
+        None => vec![],
+    }
+}
+
+fn load_env_files(table: &mut HashMap<&str, &str>, files: Vec<String>, project: &Path) {
+    for file in files {
+        let path = project.join(&file);
+        let entries = match dotenvy::from_path_iter(&path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::warn!("{err}");
+                crashln!("Cannot read env file '{file}'. Does it exist?");
+            }
+        };
+
+        for entry in entries {
+            match entry {
+                Ok((key, value)) => {
+                    log::info!("{} env.{key}: '{}' ({file})", add_icon(), value.yellow());
+                    table.insert(str!(format!("env.{key}")), str!(value));
+                }
+                Err(err) => {
+                    log::warn!("{err}");
+                    crashln!("Cannot parse env file '{file}'.");
+                }
+            }
+        }
+    }
+}
+
+fn insert_env(table: &mut HashMap<&str, &str>, values: &BTreeMap<String, Value>) {
+    for (key, value) in values {
+        let value_formatted = ternary!(
+            value.to_string().starts_with("\""),
+            trim_start_end(str!(Template::new_with_placeholder(&value.to_string(), "%{", "}").fill_with_hashmap(table))).replace("\"", "\\\""),
+            str!(Template::new_with_placeholder(&value.to_string(), "%{", "}").fill_with_hashmap(table)).replace("\"", "\\\"")
+        );
+
+        log::info!("{} env.{key}: '{}'", add_icon(), value_formatted.yellow());
+        table.insert(str!(format!("env.{}", key.clone())), str!(value_formatted));
+    }
+}
+
+/// later sources override earlier ones: project env_file, [env], task env_file, task env
+///
+/// env files are only read when `env_files` is set, the server has no access to the client's files
+pub fn create<'a>(values: Maidfile, args: &'a [String], project: PathBuf, task: Option<&String>, vars: &'a BTreeMap<String, String>, env_files: bool) -> HashMap<&'a str, &'a str> {
+    let mut table = HashMap::new();
+
+    table.insert("os.platform", env::consts::OS);
+    table.insert("os.arch", env::consts::ARCH);
+
+    log::info!("{} os.platform: '{}'", add_icon(), env::consts::OS.yellow());
+    log::info!("{} os.arch: '{}'", add_icon(), env::consts::ARCH.yellow());
+
+    match env::current_dir() {
+        Ok(path) => {
+            table.insert("dir.current", path_to_str(&path));
+            log::info!("{} dir.current: '{}'", add_icon(), path_to_str(&path).yellow());
+        }
+        Err(err) => {
+            log::warn!("{err}");
+            errorln!("Current directory could not be added as script variable.");
+        }
+    }
+
+    match home::home_dir() {
+        Some(path) => {
+            table.insert("dir.home", path_to_str(&path));
+            log::info!("{} dir.home: '{}'", add_icon(), path_to_str(&path).yellow());
+        }
+        None => {
+            errorln!("Home directory could not be added as script variable.");
+        }
+    }
+
+    let project_root = path_to_str(&project);
+    table.insert("dir.project", project_root);
+    log::info!("{} dir.project: '{}'", add_icon(), project_root.yellow());
+
+    for (pos, arg) in args.iter().enumerate() {
+        log::info!("{} arg.{pos}: '{}'", add_icon(), arg.yellow());
+        table.insert(str!(format!("arg.{pos}")), arg);
+    }
+
+    for (key, value) in vars {
+        log::info!("{} {key}: '{}'", add_icon(), value.yellow());
+        table.insert(key, value);
+    }
+
+    if let Some(project_values) = values.project.as_ref().filter(|_| env_files) {
+        load_env_files(&mut table, self::env_files(&project_values.env_file), &project);
+    }
+
+    if let Some(env) = &values.env {
+        insert_env(&mut table, env);
+    }
+
+    if let Some(task) = task.and_then(|name| values.tasks.get(name)) {
+        if env_files {
+            load_env_files(&mut table, self::env_files(&task.env_file), &project);
+        }
+
+        if let Some(env) = &task.env {
+            insert_env(&mut table, env);
+        }
+    }
+
+    log::trace!("{}", json!({ "env": table }));
+
+    table
+}
+
+pub fn envs<'a>(table: &HashMap<&'a str, &'a str>) -> Vec<(&'a str, &'a str)> { table.iter().filter_map(|(key, value)| key.strip_prefix("env.").map(|key| (key, *value))).collect() }
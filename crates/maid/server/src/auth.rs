@@ -0,0 +1,218 @@
+use crate::config::Config;
+
+use anyhow::Error;
+use chrono::{TimeZone, Utc};
+use colored::Colorize;
+use globset::Glob;
+use macros_rs::{crashln, string, ternary};
+use rocket::{http::Status, outcome::Outcome, Request};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Endpoint {
+    Health,
+    Build,
+    Cache,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredToken {
+    pub name: String,
+    pub hash: String,
+    pub created: i64,
+    pub endpoints: Vec<Endpoint>,
+    pub images: Vec<String>,
+    pub projects: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenStore {
+    #[serde(default)]
+    pub tokens: Vec<StoredToken>,
+}
+
+/// an authenticated request, restricted to the scopes of the token it used
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub name: String,
+    pub images: Vec<String>,
+    pub projects: Vec<String>,
+}
+
+fn hash(token: &str) -> String { blake3::hash(token.as_bytes()).to_hex().to_string() }
+
+fn matches(patterns: &[String], value: &str) -> bool { patterns.is_empty() || patterns.iter().any(|pattern| Glob::new(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(value))) }
+
+impl TokenStore {
+    fn path(config: &Config) -> PathBuf { PathBuf::from(&config.data_dir).join("tokens.toml") }
+
+    /// the stored tokens, an empty store when the file does not exist yet
+    fn read(config: &Config) -> Result<TokenStore, Error> {
+        match std::fs::read_to_string(TokenStore::path(config)) {
+            Ok(contents) => Ok(toml::from_str::<TokenStore>(&contents)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(TokenStore::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn load(config: &Config) -> TokenStore {
+        match TokenStore::read(config) {
+            Ok(store) => store,
+            Err(err) => crashln!("Cannot read token store.\n{err}"),
+        }
+    }
+
+    /// written to a temp file and renamed into place, so a running server never reads a half written store
+    pub fn save(&self, config: &Config) {
+        let path = TokenStore::path(config);
+        let temp = path.with_extension(format!("{}.tmp", Uuid::new_v4().simple()));
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+
+        if let Err(err) = std::fs::write(&temp, toml::to_string(self).unwrap()).and_then(|_| std::fs::rename(&temp, &path)) {
+            std::fs::remove_file(&temp).ok();
+            crashln!("Cannot write token store {:?}.\n{err}", path);
+        }
+    }
+
+    fn find(&self, token: &str) -> Option<&StoredToken> {
+        let hash = hash(token);
+        self.tokens.iter().find(|stored| stored.hash == hash)
+    }
+}
+
+impl Token {
+    pub fn allows_image(&self, image: &str) -> bool { matches(&self.images, image) }
+    pub fn allows_project(&self, project: &str) -> bool { matches(&self.projects, project) }
+}
+
+fn endpoint(request: &Request<'_>) -> Endpoint {
+    let path = request.uri().path();
+
+    match path.as_str() {
+        "/api/health" => Endpoint::Health,
+        path if path.starts_with("/api/cache") => Endpoint::Cache,
+        _ => Endpoint::Build,
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for Token {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let config = match request.rocket().state::<Config>() {
+            Some(config) => config,
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+
+        let token = match request.headers().get_one("Authorization").and_then(|header| header.strip_prefix("Bearer ")) {
+            Some(token) => token,
+            None => return Outcome::Error((Status::Unauthorized, ())),
+        };
+
+        if config.tokens.iter().any(|item| item == token) {
+            return Outcome::Success(Token {
+                name: string!("config"),
+                images: vec![],
+                projects: vec![],
+            });
+        }
+
+        let store = match TokenStore::read(config) {
+            Ok(store) => store,
+            Err(err) => {
+                log::error!("unable to read the token store: {err}");
+                return Outcome::Error((Status::InternalServerError, ()));
+            }
+        };
+
+        let stored = match store.find(token) {
+            Some(stored) => stored,
+            None => return Outcome::Error((Status::Unauthorized, ())),
+        };
+
+        if !stored.endpoints.contains(&endpoint(request)) {
+            log::warn!("token '{}' is not allowed to use {}", stored.name, request.uri().path());
+            return Outcome::Error((Status::Forbidden, ()));
+        }
+
+        Outcome::Success(Token {
+            name: stored.name.clone(),
+            images: stored.images.clone(),
+            projects: stored.projects.clone(),
+        })
+    }
+}
+
+pub fn create(config: &Config, name: &str, endpoints: &[Endpoint], images: &[String], projects: &[String]) {
+    let mut store = TokenStore::load(config);
+
+    if store.tokens.iter().any(|stored| stored.name == name) {
+        crashln!("A token named '{name}' already exists.");
+    }
+
+    for pattern in images.iter().chain(projects) {
+        if let Err(err) = Glob::new(pattern) {
+            crashln!("Invalid pattern '{pattern}'.\n{err}");
+        }
+    }
+
+    let token = format!("maid_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let endpoints = ternary!(endpoints.is_empty(), vec![Endpoint::Health, Endpoint::Build, Endpoint::Cache], endpoints.to_vec());
+
+    store.tokens.push(StoredToken {
+        name: string!(name),
+        hash: hash(&token),
+        created: Utc::now().timestamp(),
+        endpoints,
+        images: images.to_vec(),
+        projects: projects.to_vec(),
+    });
+
+    store.save(config);
+    println!("{} {}\n{}", "created token".green(), name.bright_yellow(), token.bright_cyan());
+    println!("{}", "store it now, it cannot be shown again".white());
+}
+
+pub fn list(config: &Config) {
+    let store = TokenStore::load(config);
+    let any = |items: &Vec<String>| ternary!(items.is_empty(), string!("any"), items.join(", "));
+
+    if store.tokens.is_empty() {
+        return println!("{}", "no tokens created".yellow());
+    }
+
+    for stored in store.tokens.iter() {
+        let created = Utc.timestamp_opt(stored.created, 0).single().map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        let endpoints: Vec<String> = stored.endpoints.iter().map(|endpoint| format!("{endpoint:?}").to_lowercase()).collect();
+
+        println!(
+            "{} {} {} {} {}",
+            stored.name.bright_yellow(),
+            format!("({created})").white(),
+            format!("endpoints: {}", endpoints.join(", ")).bright_cyan(),
+            format!("images: {}", any(&stored.images)).bright_blue(),
+            format!("projects: {}", any(&stored.projects)).bright_magenta()
+        );
+    }
+}
+
+pub fn revoke(config: &Config, name: &str) {
+    let mut store = TokenStore::load(config);
+    let count = store.tokens.len();
+
+    store.tokens.retain(|stored| stored.name != name);
+
+    if store.tokens.len() == count {
+        crashln!("Maid could not find the token '{name}'.");
+    }
+
+    store.save(config);
+    println!("{} {}", "revoked token".green(), name.bright_yellow());
+}
@@ -0,0 +1,156 @@
+use crate::helpers;
+
+use anyhow::{anyhow, Error};
+use flate2::read::GzDecoder;
+use maid_protocol::{is_hash, FileEntry};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tar::{Archive, Builder, Header};
+use uuid::Uuid;
+
+/// blobs no manifest referenced for this long are removed
+const UNUSED_AFTER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// a project's store is checked for unused blobs at most this often
+const PRUNE_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// file contents of previous builds, content addressed and kept per project below `<data_dir>/blobs`
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+fn age(path: &Path) -> Option<Duration> { path.metadata().and_then(|meta| meta.modified()).ok().and_then(|modified| modified.elapsed().ok()) }
+
+fn touch(path: &Path) {
+    if let Err(err) = File::options().write(true).open(path).and_then(|file| file.set_modified(SystemTime::now())) {
+        log::debug!("unable to touch {}: {err}", path.display());
+    }
+}
+
+impl BlobStore {
+    pub fn new(data_dir: &str) -> BlobStore { BlobStore { dir: PathBuf::from(data_dir).join("blobs") } }
+
+    fn project(&self, project: &str) -> PathBuf { self.dir.join(helpers::file::project_dir(project)) }
+
+    fn prune(&self, dir: &Path) {
+        let marker = dir.join(".pruned");
+        if age(&marker).is_some_and(|age| age < PRUNE_EVERY) {
+            return;
+        }
+
+        let Ok(entries) = std::fs::read_dir(dir) else { return };
+        let mut removed = 0;
+
+        for entry in entries.flatten() {
+            let unused = age(&entry.path()).is_some_and(|age| age > UNUSED_AFTER);
+
+            if unused && is_hash(&entry.file_name().to_string_lossy()) && std::fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+            }
+        }
+
+        if removed > 0 {
+            log::info!("removed {removed} unused blobs from {}", dir.display());
+        }
+
+        if let Err(err) = File::create(&marker) {
+            log::warn!("unable to write {}: {err}", marker.display());
+        }
+    }
+
+    /// hashes of the manifest the project's store does not have, blobs it does have are marked as used
+    pub fn missing(&self, project: &str, files: &[FileEntry]) -> Result<Vec<String>, Error> {
+        let dir = self.project(project);
+        let mut missing = BTreeSet::new();
+
+        std::fs::create_dir_all(&dir)?;
+        self.prune(&dir);
+
+        for file in files {
+            helpers::file::relative(&file.path)?;
+
+            if !is_hash(&file.hash) {
+                return Err(anyhow!("invalid hash '{}' for '{}'", file.hash, file.path));
+            }
+
+            let blob = dir.join(&file.hash);
+
+            if blob.is_file() {
+                touch(&blob);
+            } else {
+                missing.insert(file.hash.clone());
+            }
+        }
+
+        Ok(missing.into_iter().collect())
+    }
+
+    /// moves the blobs of an uploaded archive into the project's store, verifying each against its name
+    pub fn store(&self, project: &str, archive: &Path, missing: &[String]) -> Result<(), Error> {
+        let dir = self.project(project);
+        let mut archive = Archive::new(GzDecoder::new(File::open(archive)?));
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let hash = entry.path()?.to_string_lossy().to_string();
+
+            if !missing.contains(&hash) {
+                return Err(anyhow!("archive contains unexpected blob '{hash}'"));
+            }
+
+            let partial = dir.join(format!("{hash}.{}.part", Uuid::new_v4().simple()));
+            let mut file = File::create(&partial)?;
+            let mut hasher = blake3::Hasher::new();
+            let mut buffer = vec![0; 64 * 1024];
+
+            loop {
+                let read = entry.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+
+                hasher.update(&buffer[..read]);
+                file.write_all(&buffer[..read])?;
+            }
+
+            if hasher.finalize().to_hex().as_str() != hash {
+                std::fs::remove_file(&partial)?;
+                return Err(anyhow!("blob '{hash}' does not match its contents"));
+            }
+
+            std::fs::rename(&partial, dir.join(&hash))?;
+        }
+
+        match missing.iter().find(|hash| !dir.join(hash).is_file()) {
+            Some(hash) => Err(anyhow!("archive is missing blob '{hash}'")),
+            None => Ok(()),
+        }
+    }
+
+    /// writes a tar of the manifest's files from the project's store, removed by the caller once unpacked
+    pub fn assemble(&self, project: &str, files: &[FileEntry]) -> Result<PathBuf, Error> {
+        let dir = self.project(project);
+        let path = dir.join(format!("{}.tar", Uuid::new_v4().simple()));
+        let mut builder = Builder::new(File::create(&path)?);
+
+        for file in files {
+            let blob = File::open(dir.join(&file.hash))?;
+            let mut header = Header::new_gnu();
+
+            header.set_size(blob.metadata()?.len());
+            header.set_mode(file.mode);
+            header.set_mtime(file.modified);
+            header.set_cksum();
+
+            builder.append_data(&mut header, &file.path, blob)?;
+        }
+
+        builder.into_inner()?.sync_all()?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::auth::Token;
+use crate::helpers;
+
+use global_placeholders::global;
+use macros_rs::then;
+use rocket::data::{Data, ToByteUnit};
+use rocket::{fs::NamedFile, get, http::Status, put};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+fn valid(key: &str) -> bool { key.len() == 64 && key.chars().all(|char| char.is_ascii_hexdigit()) }
+
+/// entries are kept per project, so a token can only reach the artifacts of the projects it is scoped to
+fn project_dir(project: &str) -> PathBuf { PathBuf::from(global!("maid.cache_dir")).join(helpers::file::project_dir(project)) }
+
+#[get("/api/cache/<key>?<project>")]
+pub async fn get(key: &str, project: Option<&str>, token: Token) -> Result<NamedFile, Status> {
+    let project = project.unwrap_or_default();
+
+    then!(!valid(key), return Err(Status::BadRequest));
+    then!(!token.allows_project(project), return Err(Status::NotFound));
+
+    match NamedFile::open(project_dir(project).join(format!("{key}.tgz"))).await {
+        Ok(file) => {
+            log::info!("cache hit for {key} (project={project})");
+            Ok(file)
+        }
+        Err(_) => Err(Status::NotFound),
+    }
+}
+
+#[put("/api/cache/<key>?<project>", data = "<data>")]
+pub async fn put(key: &str, project: Option<&str>, data: Data<'_>, token: Token) -> Status {
+    let project = project.unwrap_or_default();
+
+    then!(!valid(key), return Status::BadRequest);
+
+    if !token.allows_project(project) {
+        log::warn!("token '{}' denied storing cache entry {key} (project={project})", token.name);
+        return Status::Forbidden;
+    }
+
+    let dir = project_dir(project);
+    let path = dir.join(format!("{key}.tgz"));
+    let temp = dir.join(format!("{key}.{}.part", Uuid::new_v4().simple()));
+
+    if let Err(err) = tokio::fs::create_dir_all(&dir).await {
+        log::error!("unable to create cache dir: {err}");
+        return Status::InternalServerError;
+    }
+
+    let file = match data.open(1.gibibytes()).into_file(&temp).await {
+        Ok(file) => file,
+        Err(err) => {
+            log::error!("unable to store cache entry {key}: {err}");
+            tokio::fs::remove_file(&temp).await.ok();
+            return Status::InternalServerError;
+        }
+    };
+
+    if !file.is_complete() {
+        tokio::fs::remove_file(&temp).await.ok();
+        return Status::PayloadTooLarge;
+    }
+
+    match tokio::fs::rename(&temp, &path).await {
+        Ok(_) => {
+            log::info!("stored cache entry {key}");
+            Status::Created
+        }
+        Err(err) => {
+            log::error!("unable to store cache entry {key}: {err}");
+            Status::InternalServerError
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::executor::Backend;
+
+use macros_rs::{crashln, string};
+use serde::{Deserialize, Serialize};
+use std::{env, path::Path};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub address: String,
+    pub port: u16,
+    pub temp_dir: String,
+    pub data_dir: String,
+    pub tokens: Vec<String>,
+    pub max_builds: usize,
+    pub executor: Backend,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+    pub docker: Docker,
+    pub local: Local,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Tls {
+    pub certs: String,
+    pub key: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Docker {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub timeout: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Local {
+    /// lets tasks pick the local executor with `remote.executor = "local"`
+    ///
+    /// this grants every token holder shell access to the server, only confined by the chroot into `root`
+    /// and the unprivileged `uid`/`gid` the builds run as
+    pub enabled: bool,
+    /// directory builds are chrooted into, required by the local executor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// unprivileged user builds run as after the chroot, required by the local executor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// unprivileged group builds run as after the chroot, required by the local executor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+}
+
+// config values given as command line flags, these take precedence over the config file and the environment,
+// tokens are left out on purpose as flags show up in the process list
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct Overrides {
+    #[arg(global = true, long, help = "Address to listen on")]
+    pub address: Option<String>,
+    #[arg(global = true, long, help = "Port to listen on")]
+    pub port: Option<u16>,
+    #[arg(global = true, long, help = "Directory for uploads, workspaces and the build cache")]
+    pub temp_dir: Option<String>,
+    #[arg(global = true, long, help = "Directory for tokens, blobs and job records")]
+    pub data_dir: Option<String>,
+    #[arg(global = true, long, help = "Maximum number of builds running at once")]
+    pub max_builds: Option<usize>,
+    #[arg(global = true, long, value_enum, help = "Default executor for remote builds")]
+    pub executor: Option<Backend>,
+    #[arg(global = true, long, requires = "tls_key", help = "Path to the TLS certificate chain")]
+    pub tls_certs: Option<String>,
+    #[arg(global = true, long, requires = "tls_certs", help = "Path to the TLS private key")]
+    pub tls_key: Option<String>,
+    #[arg(global = true, long, help = "Docker host to connect to")]
+    pub docker_host: Option<String>,
+    #[arg(global = true, long, help = "Docker socket to connect to")]
+    pub docker_socket: Option<String>,
+    #[arg(global = true, long, help = "Docker connection timeout in seconds")]
+    pub docker_timeout: Option<u64>,
+    #[arg(global = true, long, help = "Directory local builds are chrooted into")]
+    pub local_root: Option<String>,
+    #[arg(global = true, long, help = "User local builds run as")]
+    pub local_uid: Option<u32>,
+    #[arg(global = true, long, help = "Group local builds run as")]
+    pub local_gid: Option<u32>,
+}
+
+impl Local {
+    /// builds are only run on linux, chrooted and as a user other than root
+    pub fn sandboxed(&self) -> bool { cfg!(target_os = "linux") && self.root.is_some() && self.uid.is_some_and(|uid| uid != 0) && self.gid.is_some_and(|gid| gid != 0) }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: string!("127.0.0.1"),
+            port: 3500,
+            temp_dir: string!("/usr/tmp/maid"),
+            data_dir: string!("/var/lib/maid"),
+            tokens: vec![],
+            max_builds: 4,
+            executor: Backend::Docker,
+            tls: None,
+            docker: Docker::default(),
+            local: Local::default(),
+        }
+    }
+}
+
+impl Default for Docker {
+    fn default() -> Self { Docker { socket: None, host: None, timeout: 120 } }
+}
+
+fn var(name: &str) -> Option<String> { env::var(name).ok().filter(|value| !value.trim().is_empty()) }
+
+impl Config {
+    /// resolves the config, later sources win: defaults, the config file, `MAID_SERVER_*` variables, command line flags
+    pub fn load(path: &Option<String>, overrides: &Overrides) -> Config {
+        let path = path.clone().or_else(|| var("MAID_SERVER_CONFIG"));
+
+        let mut config = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => Config::parse(path, &contents),
+                Err(err) => crashln!("Cannot read config '{path}'.\n{err}"),
+            },
+            None if Path::new("maid_server.toml").exists() => match std::fs::read_to_string("maid_server.toml") {
+                Ok(contents) => Config::parse("maid_server.toml", &contents),
+                Err(err) => crashln!("Cannot read config 'maid_server.toml'.\n{err}"),
+            },
+            None => Config::default(),
+        };
+
+        config.apply_env();
+        config.apply_overrides(overrides);
+        return config;
+    }
+
+    fn parse(path: &str, contents: &str) -> Config {
+        match toml::from_str::<Config>(contents) {
+            Ok(config) => config,
+            Err(err) => crashln!("Cannot parse config '{path}'.\n{err}"),
+        }
+    }
+
+    fn apply_env(&mut self) {
+        if let Some(address) = var("MAID_SERVER_ADDRESS") {
+            self.address = address;
+        }
+
+        if let Some(port) = var("MAID_SERVER_PORT") {
+            self.port = match port.parse() {
+                Ok(port) => port,
+                Err(_) => crashln!("MAID_SERVER_PORT must be a valid port, got '{port}'."),
+            };
+        }
+
+        if let Some(max_builds) = var("MAID_SERVER_MAX_BUILDS") {
+            self.max_builds = match max_builds.parse() {
+                Ok(max_builds) => max_builds,
+                Err(_) => crashln!("MAID_SERVER_MAX_BUILDS must be a number, got '{max_builds}'."),
+            };
+        }
+
+        if let Some(executor) = var("MAID_SERVER_EXECUTOR") {
+            self.executor = match executor.as_str() {
+                "docker" => Backend::Docker,
+                "local" => Backend::Local,
+                _ => crashln!("MAID_SERVER_EXECUTOR must be 'docker' or 'local', got '{executor}'."),
+            };
+        }
+
+        if let Some(temp_dir) = var("MAID_SERVER_TEMP_DIR") {
+            self.temp_dir = temp_dir;
+        }
+
+        if let Some(data_dir) = var("MAID_SERVER_DATA_DIR") {
+            self.data_dir = data_dir;
+        }
+
+        if let Some(tokens) = var("MAID_SERVER_TOKENS") {
+            self.tokens = tokens.split(',').map(|token| string!(token.trim())).filter(|token| !token.is_empty()).collect();
+        }
+
+        match (var("MAID_SERVER_TLS_CERTS"), var("MAID_SERVER_TLS_KEY")) {
+            (Some(certs), Some(key)) => self.tls = Some(Tls { certs, key }),
+            (None, None) => {}
+            _ => crashln!("MAID_SERVER_TLS_CERTS and MAID_SERVER_TLS_KEY must be set together."),
+        }
+
+        if let Some(host) = var("MAID_SERVER_DOCKER_HOST") {
+            self.docker.host = Some(host);
+        }
+
+        if let Some(socket) = var("MAID_SERVER_DOCKER_SOCKET") {
+            self.docker.socket = Some(socket);
+        }
+
+        if let Some(root) = var("MAID_SERVER_LOCAL_ROOT") {
+            self.local.root = Some(root);
+        }
+
+        if let Some(uid) = var("MAID_SERVER_LOCAL_UID") {
+            self.local.uid = match uid.parse() {
+                Ok(uid) => Some(uid),
+                Err(_) => crashln!("MAID_SERVER_LOCAL_UID must be a number, got '{uid}'."),
+            };
+        }
+
+        if let Some(gid) = var("MAID_SERVER_LOCAL_GID") {
+            self.local.gid = match gid.parse() {
+                Ok(gid) => Some(gid),
+                Err(_) => crashln!("MAID_SERVER_LOCAL_GID must be a number, got '{gid}'."),
+            };
+        }
+    }
+
+    fn apply_overrides(&mut self, overrides: &Overrides) {
+        let overrides = overrides.clone();
+
+        self.address = overrides.address.unwrap_or(self.address.clone());
+        self.port = overrides.port.unwrap_or(self.port);
+        self.temp_dir = overrides.temp_dir.unwrap_or(self.temp_dir.clone());
+        self.data_dir = overrides.data_dir.unwrap_or(self.data_dir.clone());
+        self.max_builds = overrides.max_builds.unwrap_or(self.max_builds);
+        self.executor = overrides.executor.unwrap_or(self.executor);
+        self.docker.timeout = overrides.docker_timeout.unwrap_or(self.docker.timeout);
+
+        if let (Some(certs), Some(key)) = (overrides.tls_certs, overrides.tls_key) {
+            self.tls = Some(Tls { certs, key });
+        }
+
+        self.docker.host = overrides.docker_host.or(self.docker.host.clone());
+        self.docker.socket = overrides.docker_socket.or(self.docker.socket.clone());
+        self.local.root = overrides.local_root.or(self.local.root.clone());
+        self.local.uid = overrides.local_uid.or(self.local.uid);
+        self.local.gid = overrides.local_gid.or(self.local.gid);
+    }
+
+    pub fn cache_dir(&self) -> String { format!("{}/cache", self.temp_dir.trim_end_matches('/')) }
+}
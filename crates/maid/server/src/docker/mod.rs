@@ -1,2 +1 @@
-pub mod run;
-pub mod container;
\ No newline at end of file
+pub mod container;
@@ -0,0 +1,121 @@
+use super::Executor;
+
+use anyhow::{anyhow, Error};
+use futures_util::{stream::TryStreamExt, StreamExt};
+use macros_rs::string;
+use std::path::Path;
+use tokio::sync::mpsc::UnboundedSender;
+
+use bollard::{
+    container::{Config, DownloadFromContainerOptions, RemoveContainerOptions, UploadToContainerOptions},
+    exec::{CreateExecOptions, StartExecResults},
+    image::CreateImageOptions,
+    Docker,
+};
+
+/// runs the build in a fresh container of the task's image
+pub struct DockerExecutor<'a> {
+    docker: &'a Result<Docker, Error>,
+    id: Option<String>,
+}
+
+impl<'a> DockerExecutor<'a> {
+    pub fn new(docker: &'a Result<Docker, Error>) -> DockerExecutor<'a> { DockerExecutor { docker, id: None } }
+
+    fn socket(&self) -> Result<&'a Docker, Error> { self.docker.as_ref().map_err(|err| anyhow!("docker is unavailable: {err}")) }
+
+    fn container(&self) -> Result<&str, Error> { self.id.as_deref().ok_or_else(|| anyhow!("container has not been created")) }
+}
+
+impl Executor for DockerExecutor<'_> {
+    fn workdir(&self) -> String { string!("/opt") }
+
+    async fn prepare(&mut self, image: &str, progress: UnboundedSender<String>) -> Result<(), Error> {
+        let socket = self.socket()?;
+
+        log::info!("pulling image {image}");
+
+        let image_config = CreateImageOptions {
+            from_image: image,
+            ..Default::default()
+        };
+
+        let mut pull = socket.create_image(Some(image_config), None, None);
+
+        while let Some(message) = pull.next().await {
+            let message = message?;
+            let _ = progress.send(format!("{} {}", message.status.unwrap_or_else(|| string!("Waiting")), message.progress.unwrap_or_default()));
+        }
+
+        let config = Config {
+            image: Some(image),
+            tty: Some(true),
+            ..Default::default()
+        };
+
+        let id = socket.create_container::<&str, &str>(None, config).await?.id;
+        log::info!("created container");
+
+        self.id = Some(id.clone());
+        socket.start_container::<String>(&id, None).await?;
+        log::info!("started container");
+
+        Ok(())
+    }
+
+    async fn upload(&mut self, archive: &Path) -> Result<(), Error> {
+        let upload_options = UploadToContainerOptions { path: "/opt", ..Default::default() };
+        let archive = tokio::fs::read(archive).await?;
+
+        self.socket()?.upload_to_container(self.container()?, Some(upload_options), archive.into()).await?;
+        log::info!("wrote tarfile to container");
+
+        Ok(())
+    }
+
+    async fn run(&mut self, shell: &str, script: &str, output: UnboundedSender<String>) -> Result<i64, Error> {
+        let socket = self.socket()?;
+
+        let exec = socket
+            .create_exec(
+                self.container()?,
+                CreateExecOptions {
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    cmd: Some(vec![shell, "-c", script]),
+                    ..Default::default()
+                },
+            )
+            .await?
+            .id;
+
+        if let StartExecResults::Attached { output: mut stream, .. } = socket.start_exec(&exec, None).await? {
+            while let Some(msg) = stream.next().await {
+                match msg {
+                    Ok(msg) => {
+                        let _ = output.send(msg.to_string());
+                    }
+                    Err(err) => log::error!("{err}"),
+                }
+            }
+        }
+
+        Ok(socket.inspect_exec(&exec).await?.exit_code.unwrap_or(1))
+    }
+
+    async fn download(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let options = DownloadFromContainerOptions { path: format!("/opt/{path}") };
+        let bytes = self.socket()?.download_from_container(self.container()?, Some(options)).map_ok(|chunk| chunk.to_vec()).try_concat().await?;
+
+        Ok(bytes)
+    }
+
+    async fn cleanup(&mut self) -> Result<(), Error> {
+        if let Some(id) = self.id.take() {
+            self.socket()?.remove_container(&id, Some(RemoveContainerOptions { force: true, ..Default::default() })).await?;
+            log::info!("removed container");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use super::Executor;
+use crate::{config::Local, helpers};
+
+use anyhow::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// where each job's workspace is mounted inside `local.root`
+const WORKDIR: &str = "/maid";
+
+/// runs the build chrooted into `local.root` as the unprivileged `local.uid`/`local.gid`
+///
+/// every build gets its own mount namespace with only its workspace bind mounted at `/maid`,
+/// the workspaces themselves live outside the root so concurrent jobs cannot reach each other's files
+pub struct LocalExecutor {
+    root: PathBuf,
+    workspace: PathBuf,
+    uid: u32,
+    gid: u32,
+    pid: Option<u32>,
+}
+
+/// turns a libc return code into the error of the failed call
+#[cfg(target_os = "linux")]
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+fn chown_all(path: &Path, uid: u32, gid: u32) -> std::io::Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+
+    if path.symlink_metadata()?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_all(&entry?.path(), uid, gid)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl LocalExecutor {
+    /// `select` only picks this executor once `Local::sandboxed` holds, the fallbacks fail the build instead of running it as root
+    pub fn new(local: &Local, temp_dir: &str, job: &str) -> LocalExecutor {
+        LocalExecutor {
+            root: PathBuf::from(local.root.as_deref().unwrap_or_default()),
+            workspace: Path::new(temp_dir).join("workspaces").join(job),
+            uid: local.uid.unwrap_or(u32::MAX),
+            gid: local.gid.unwrap_or(u32::MAX),
+            pid: None,
+        }
+    }
+
+    fn command(&self, shell: &str, script: &str) -> tokio::process::Command {
+        let mut command = std::process::Command::new(shell);
+        command.arg("-c").arg(script).env_clear().env("PATH", "/usr/local/bin:/usr/bin:/bin").env("HOME", WORKDIR);
+        command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::{ffi::CString, os::unix::ffi::OsStrExt, os::unix::process::CommandExt};
+
+            let cstring = |path: &Path| CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+            let (root, mountpoint, workspace) = (cstring(&self.root), cstring(&self.root.join("maid")), cstring(&self.workspace));
+            let (slash, workdir) = (cstring(Path::new("/")), cstring(Path::new(WORKDIR)));
+            let (uid, gid) = (self.uid, self.gid);
+
+            // runs in the forked child, right before the shell is executed, so only plain syscalls are made here
+            unsafe {
+                command.pre_exec(move || {
+                    let null = std::ptr::null();
+
+                    check(libc::unshare(libc::CLONE_NEWNS))?;
+                    check(libc::mount(null, slash.as_ptr(), null, libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()))?;
+                    check(libc::mount(workspace.as_ptr(), mountpoint.as_ptr(), null, libc::MS_BIND, std::ptr::null()))?;
+                    check(libc::chroot(root.as_ptr()))?;
+                    check(libc::chdir(workdir.as_ptr()))?;
+                    check(libc::setgroups(0, std::ptr::null()))?;
+                    check(libc::setgid(gid))?;
+                    check(libc::setuid(uid))
+                });
+            }
+        }
+
+        let mut command = tokio::process::Command::from(command);
+        command.kill_on_drop(true);
+
+        return command;
+    }
+
+    fn kill(&mut self) {
+        if let Some(pid) = self.pid.take() {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGKILL);
+            }
+
+            log::info!("stopped process group {pid}");
+        }
+    }
+}
+
+fn forward<R: AsyncRead + Unpin + Send + 'static>(reader: R, output: UnboundedSender<String>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let _ = output.send(format!("{line}\n"));
+        }
+    })
+}
+
+impl Executor for LocalExecutor {
+    fn workdir(&self) -> String { String::from(WORKDIR) }
+
+    async fn prepare(&mut self, image: &str, _progress: UnboundedSender<String>) -> Result<(), Error> {
+        log::debug!("local executor ignores image {image}");
+
+        std::fs::create_dir_all(self.root.join("maid"))?;
+        std::fs::create_dir_all(&self.workspace)?;
+        log::info!("created workspace {}", self.workspace.display());
+
+        Ok(())
+    }
+
+    async fn upload(&mut self, archive: &Path) -> Result<(), Error> {
+        tar::Archive::new(File::open(archive)?).unpack(&self.workspace)?;
+        chown_all(&self.workspace, self.uid, self.gid)?;
+        log::info!("unpacked archive into workspace");
+
+        Ok(())
+    }
+
+    async fn run(&mut self, shell: &str, script: &str, output: UnboundedSender<String>) -> Result<i64, Error> {
+        let mut child = self.command(shell, script).spawn()?;
+        self.pid = child.id();
+
+        let stdout = child.stdout.take().map(|stdout| forward(stdout, output.clone()));
+        let stderr = child.stderr.take().map(|stderr| forward(stderr, output));
+        let status = child.wait().await?;
+
+        for reader in [stdout, stderr].into_iter().flatten() {
+            let _ = reader.await;
+        }
+
+        self.pid = None;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return Ok(128 + signal as i64);
+            }
+        }
+
+        Ok(status.code().unwrap_or(1) as i64)
+    }
+
+    async fn download(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let relative = helpers::file::relative(path)?;
+        let source = self.workspace.join(relative);
+        let name = source.file_name().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+        let mut archive = tar::Builder::new(Vec::new());
+
+        // the build owns the workspace, links it left behind must not make the server read files outside of it
+        archive.follow_symlinks(false);
+
+        match source.symlink_metadata()?.is_dir() {
+            true => archive.append_dir_all(&name, &source)?,
+            false => archive.append_path_with_name(&source, &name)?,
+        };
+
+        Ok(archive.into_inner()?)
+    }
+
+    async fn cleanup(&mut self) -> Result<(), Error> {
+        self.kill();
+
+        if self.workspace.exists() {
+            std::fs::remove_dir_all(&self.workspace)?;
+            log::info!("removed workspace {}", self.workspace.display());
+        }
+
+        Ok(())
+    }
+}
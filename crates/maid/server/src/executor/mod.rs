@@ -0,0 +1,57 @@
+pub mod docker;
+pub mod local;
+pub mod run;
+
+use crate::config::Config;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Docker,
+    Local,
+}
+
+/// the steps of one remote build, every build gets a fresh executor
+pub trait Executor {
+    /// directory the workspace is unpacked into, as seen by the script
+    fn workdir(&self) -> String;
+
+    /// creates the isolated workspace, reporting progress such as image pulls
+    async fn prepare(&mut self, image: &str, progress: UnboundedSender<String>) -> Result<(), Error>;
+
+    /// unpacks a tar of the task's `push` paths into the workdir
+    async fn upload(&mut self, archive: &Path) -> Result<(), Error>;
+
+    /// runs `shell -c script`, streaming its output, and returns the exit code
+    async fn run(&mut self, shell: &str, script: &str, output: UnboundedSender<String>) -> Result<i64, Error>;
+
+    /// tar archive of a path relative to the workdir
+    async fn download(&mut self, path: &str) -> Result<Vec<u8>, Error>;
+
+    /// stops anything still running and removes the workspace
+    async fn cleanup(&mut self) -> Result<(), Error>;
+}
+
+/// picks the executor for a build, a task's `remote.executor` overrides the server default
+///
+/// the local executor is refused unless it is sandboxed, builds never run directly on the host
+pub fn select(config: &Config, requested: &Option<String>) -> Result<Backend, String> {
+    let backend = match requested.as_deref() {
+        None => config.executor,
+        Some("docker") => Backend::Docker,
+        Some("local") if config.executor == Backend::Local || config.local.enabled => Backend::Local,
+        Some("local") => return Err(String::from("the local executor is not enabled on this server")),
+        Some(executor) => return Err(format!("unknown executor '{executor}', expected 'docker' or 'local'")),
+    };
+
+    match backend {
+        Backend::Local if !config.local.sandboxed() => Err(String::from("the local executor is not sandboxed on this server")),
+        backend => Ok(backend),
+    }
+}
@@ -0,0 +1,358 @@
+use super::{Backend, Executor};
+use crate::{
+    auth::Token,
+    blobs::BlobStore,
+    config::Config,
+    jobs::JobStore,
+    upload::{Upload, UploadStore},
+};
+
+use anyhow::{anyhow, Error};
+use flate2::{write::GzEncoder, Compression};
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use macros_rs::{str, string, then};
+use maid_protocol::{table, Chunk, ClientMessage, ConnectionData, FileEntry, Level, Response, VERSION};
+use rocket_ws::{stream::DuplexStream, Message};
+use std::{future::Future, io::Write, path::PathBuf};
+use text_placeholder::Template;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio_util::sync::CancellationToken;
+
+/// corrupt chunks tolerated per upload before the build fails
+const MAX_REJECTED: u32 = 5;
+
+/// workspace frames forwarded from the socket to the build
+enum Frame {
+    Manifest(Vec<FileEntry>),
+    Upload { id: String, size: u64 },
+    Chunk(Vec<u8>),
+}
+
+async fn reject(stream: &mut DuplexStream, message: String) -> Result<(), Error> {
+    stream.send(Message::text(Response::message(Level::Fatal, message))).await?;
+    stream.send(Message::text(Response::done(1))).await?;
+
+    Ok(())
+}
+
+/// next text frame from the client, `None` once it disconnected
+async fn next(stream: &mut DuplexStream) -> Result<Option<Result<ClientMessage, serde_json::Error>>, Error> {
+    loop {
+        match stream.next().await {
+            Some(Ok(Message::Text(text))) => return Ok(Some(serde_json::from_str::<ClientMessage>(&text))),
+            Some(Ok(Message::Close(_))) | None => return Ok(None),
+            Some(Ok(_)) => {}
+            Some(Err(err)) => return Err(err.into()),
+        }
+    }
+}
+
+/// checks the client's protocol version, then waits for the task it sends
+pub async fn receive(stream: &mut DuplexStream, token: &Token, config: &Config) -> Result<Option<(ConnectionData, Backend)>, Error> {
+    match next(stream).await? {
+        Some(Ok(ClientMessage::Hello { version, protocol })) => {
+            if let Err(err) = maid_protocol::compatible("maid_server", "maid", &version, protocol) {
+                log::warn!("rejected client: {err}");
+                reject(stream, err).await?;
+
+                return Ok(None);
+            }
+
+            log::info!("client handshake (maid={version}, protocol={protocol})");
+            stream.send(Message::text(Response::hello())).await?;
+        }
+        Some(_) => {
+            log::warn!("rejected client without handshake");
+            reject(stream, format!("this maid client is too old for maid_server {VERSION}, please upgrade maid")).await?;
+
+            return Ok(None);
+        }
+        None => return Ok(None),
+    }
+
+    let parsed = loop {
+        match next(stream).await? {
+            Some(Ok(ClientMessage::Task(parsed))) => break *parsed,
+            Some(Ok(ClientMessage::Cancel)) | None => return Ok(None),
+            Some(Ok(ClientMessage::Hello { .. })) => log::warn!("ignoring repeated handshake"),
+            Some(Ok(ClientMessage::Manifest { .. } | ClientMessage::Upload { .. })) => log::warn!("ignoring workspace before the task"),
+            Some(Err(err)) => log::error!("Failed to deserialize JSON: {:?}", err),
+        }
+    };
+
+    let image = &parsed.info.remote.image;
+    let project = parsed.maidfile.project.as_ref().and_then(|project| project.name.clone()).unwrap_or_default();
+
+    if !token.allows_image(image) || !token.allows_project(&project) {
+        log::warn!("token '{}' denied (project={project}, image={image})", token.name);
+        reject(stream, format!("token '{}' is not allowed to build project '{project}' with image '{image}'", token.name)).await?;
+
+        return Ok(None);
+    }
+
+    match super::select(config, &parsed.info.remote.executor) {
+        Ok(backend) => Ok(Some((parsed, backend))),
+        Err(err) => {
+            log::warn!("rejected build of '{}': {err}", parsed.info.name);
+            reject(stream, err).await?;
+
+            Ok(None)
+        }
+    }
+}
+
+/// reads client frames during a build, a cancel frame or a disconnect cancels the job
+async fn watch(mut source: SplitStream<DuplexStream>, frames: mpsc::Sender<Frame>, cancel: CancellationToken) {
+    loop {
+        match source.next().await {
+            Some(Ok(Message::Binary(data))) => then!(frames.send(Frame::Chunk(data)).await.is_err(), break),
+            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                Ok(ClientMessage::Manifest { files }) => then!(frames.send(Frame::Manifest(files)).await.is_err(), break),
+                Ok(ClientMessage::Upload { id, size }) => then!(frames.send(Frame::Upload { id, size }).await.is_err(), break),
+                Ok(ClientMessage::Cancel) => {
+                    log::warn!("client cancelled the build");
+                    break;
+                }
+                _ => log::debug!("ignoring message during build"),
+            },
+            Some(Ok(Message::Close(_))) | None => {
+                log::warn!("client disconnected");
+                break;
+            }
+            Some(Err(err)) => {
+                log::warn!("client connection failed: {err}");
+                break;
+            }
+            Some(Ok(_)) => {}
+        }
+    }
+
+    cancel.cancel();
+}
+
+struct Build<'a> {
+    stream: SplitSink<DuplexStream, Message>,
+    jobs: &'a JobStore,
+    uploads: &'a UploadStore,
+    blobs: &'a BlobStore,
+    job: &'a str,
+    cancel: CancellationToken,
+}
+
+impl<'a> Build<'a> {
+    async fn send(&mut self, response: Response) -> Result<(), Error> {
+        self.stream.send(Message::text(response)).await?;
+        Ok(())
+    }
+
+    async fn output(&mut self, line: String, level: Level, silent: bool) {
+        self.jobs.append(self.job, &line);
+        then!(!line.ends_with('\n'), self.jobs.append(self.job, "\n"));
+        then!(silent, return);
+
+        let output_message = Response::message(level, line);
+
+        // a closed socket cancels the build, which the step loop picks up next
+        if let Err(err) = self.send(output_message).await {
+            log::debug!("unable to send output: {err}");
+        }
+    }
+
+    /// drives one executor step, forwarding its output until it completes or the build is cancelled
+    async fn forward<T>(&mut self, step: impl Future<Output = Result<T, Error>>, mut lines: UnboundedReceiver<String>, level: Level, silent: bool) -> Result<Option<T>, Error> {
+        let cancel = self.cancel.clone();
+        tokio::pin!(step);
+
+        loop {
+            tokio::select! {
+                result = &mut step => {
+                    while let Ok(line) = lines.try_recv() {
+                        self.output(line, level, silent).await;
+                    }
+
+                    return result.map(Some);
+                }
+                Some(line) = lines.recv() => self.output(line, level, silent).await,
+                _ = cancel.cancelled() => return Ok(None),
+            }
+        }
+    }
+
+    /// next workspace frame, `None` once the build is cancelled
+    async fn frame(&self, frames: &mut mpsc::Receiver<Frame>) -> Option<Frame> {
+        tokio::select! {
+            frame = frames.recv() => frame,
+            _ = self.cancel.cancelled() => None,
+        }
+    }
+
+    /// receives the workspace archive chunk by chunk, asking the client to resume at the last good offset
+    async fn receive(&mut self, frames: &mut mpsc::Receiver<Frame>) -> Result<Option<Upload<'a>>, Error> {
+        let mut upload = loop {
+            match self.frame(frames).await {
+                Some(Frame::Upload { id, size }) => break self.uploads.begin(&id, size)?,
+                Some(Frame::Manifest(_) | Frame::Chunk(_)) => continue,
+                None => return Ok(None),
+            }
+        };
+
+        let mut rejected = 0;
+        self.send(Response::resume(upload.offset)).await?;
+
+        while !upload.complete() {
+            let frame = match self.frame(frames).await {
+                Some(Frame::Chunk(frame)) => frame,
+                Some(Frame::Manifest(_) | Frame::Upload { .. }) => continue,
+                None => return Ok(None),
+            };
+
+            match Chunk::decode(&frame) {
+                Ok(chunk) if chunk.offset == upload.offset => upload.write(chunk.data)?,
+                // chunks the client sent before it rewound to the offset asked for
+                Ok(chunk) => log::debug!("skipping chunk at offset {} (expected {})", chunk.offset, upload.offset),
+                Err(err) if rejected < MAX_REJECTED => {
+                    log::warn!("{err}, asking the client to resume at {}", upload.offset);
+                    rejected += 1;
+                    self.send(Response::resume(upload.offset)).await?;
+                }
+                Err(err) => return Err(anyhow!("{err}, giving up after {MAX_REJECTED} corrupt chunks")),
+            }
+        }
+
+        Ok(Some(upload))
+    }
+
+    /// syncs the client's manifest against the project's blobs and returns a tar of the workspace
+    async fn sync(&mut self, frames: &mut mpsc::Receiver<Frame>, project: &str) -> Result<Option<PathBuf>, Error> {
+        let files = loop {
+            match self.frame(frames).await {
+                Some(Frame::Manifest(files)) => break files,
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        };
+
+        let missing = self.blobs.missing(project, &files)?;
+        log::info!("{} of {} files missing from the store", missing.len(), files.len());
+        self.send(Response::missing(missing.clone())).await?;
+
+        if !missing.is_empty() {
+            let upload = match self.receive(frames).await? {
+                Some(upload) => upload,
+                None => return Ok(None),
+            };
+
+            log::info!("received archive ({} bytes)", upload.size);
+            self.blobs.store(project, &upload.path, &missing)?;
+            upload.remove();
+        }
+
+        Ok(Some(self.blobs.assemble(project, &files)?))
+    }
+
+    async fn run<E: Executor>(&mut self, executor: &mut E, mut frames: mpsc::Receiver<Frame>, parsed: &ConnectionData) -> Result<Option<i64>, Error> {
+        let remote = &parsed.info.remote;
+        log::info!("preparing build (task={}, image={})", parsed.info.name, remote.image);
+
+        let (progress, lines) = mpsc::unbounded_channel();
+        then!(self.forward(executor.prepare(&remote.image, progress), lines, Level::Docker, false).await?.is_none(), return Ok(None));
+
+        self.send(Response::binary()).await?;
+
+        let project = parsed.maidfile.project.as_ref().and_then(|project| project.name.clone()).unwrap_or_default();
+        let workspace = match self.sync(&mut frames, &project).await? {
+            Some(workspace) => workspace,
+            None => return Ok(None),
+        };
+
+        let unpacked = executor.upload(&workspace).await;
+
+        if let Err(err) = std::fs::remove_file(&workspace) {
+            log::warn!("unable to remove workspace archive {}: {err}", workspace.display());
+        }
+
+        unpacked?;
+
+        let dependencies = match &parsed.maidfile.tasks[&parsed.info.name].depends {
+            Some(deps) => {
+                let mut dep_script: Vec<String> = vec![];
+                for item in deps.iter() {
+                    dep_script.push(
+                        parsed.maidfile.tasks[item]
+                            .script
+                            .as_array()
+                            .map(|arr| arr.iter().map(|val| val.as_str().unwrap_or_default()).collect::<Vec<_>>().join("\n"))
+                            .unwrap_or_default(),
+                    );
+                }
+                dep_script.join("\n")
+            }
+            None => {
+                string!("")
+            }
+        };
+
+        let workdir = executor.workdir();
+        let table = table::create(parsed.maidfile.clone(), &parsed.info.args, PathBuf::from(&workdir), Some(&parsed.info.name), &parsed.info.vars, false);
+        let script = Template::new_with_placeholder(str!(parsed.info.script.join("\n")), "%{", "}").fill_with_hashmap(&table);
+        let dependencies = Template::new_with_placeholder(str!(dependencies), "%{", "}").fill_with_hashmap(&table);
+        let command = format!("cd {workdir} && touch script.sh && echo '{dependencies}\n{script}' > script.sh && chmod +x script.sh && ./script.sh");
+
+        self.send(Response::message(Level::Build, "waiting for build to finish..")).await?;
+
+        let (output, lines) = mpsc::unbounded_channel();
+        let code = match self.forward(executor.run(&remote.shell, &command, output), lines, Level::None, remote.silent).await? {
+            Some(code) => code,
+            None => return Ok(None),
+        };
+
+        log::info!("script exited with code {code}");
+
+        if code == 0 {
+            let bytes = executor.download(&remote.pull).await?;
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+            encoder.write_all(&bytes)?;
+            let compressed_data = encoder.finish()?;
+
+            self.stream.send(Message::binary(compressed_data)).await?;
+            log::info!("sent message: binary, from [{}]", remote.pull);
+        }
+
+        self.send(Response::done(code)).await?;
+        log::info!("sent message: [done]");
+
+        Ok(Some(code))
+    }
+}
+
+/// runs the build on an executor, returning its exit code or `None` when it was cancelled
+pub async fn exec<E: Executor>(stream: DuplexStream, mut executor: E, parsed: &ConnectionData, jobs: &JobStore, uploads: &UploadStore, blobs: &BlobStore, job: &str) -> Result<Option<i64>, Error> {
+    let cancel = jobs.cancellation(job);
+    let (sink, source) = stream.split();
+    let (sender, frames) = mpsc::channel(4);
+    let watcher = tokio::spawn(watch(source, sender, cancel.clone()));
+
+    let mut build = Build { stream: sink, jobs, uploads, blobs, job, cancel };
+    let result = build.run(&mut executor, frames, parsed).await;
+    watcher.abort();
+
+    if let Err(err) = executor.cleanup().await {
+        log::error!("unable to clean up build: {err}");
+    }
+
+    let (message, code) = match &result {
+        Ok(Some(_)) => return result,
+        Ok(None) => (string!("build cancelled"), 130),
+        Err(err) => (format!("build failed: {err}"), 1),
+    };
+
+    // the client is usually gone after a cancel, so failing to tell it is expected
+    let _ = build.send(Response::message(Level::Fatal, message)).await;
+    let _ = build.send(Response::done(code)).await;
+
+    return result;
+}
@@ -1,5 +1,7 @@
+use crate::config::Config;
 use global_placeholders::init;
 
-pub fn init() {
-    init!("maid.temp_dir", "/usr/tmp/maid");
+pub fn init(config: &Config) {
+    init!("maid.temp_dir", config.temp_dir);
+    init!("maid.cache_dir", config.cache_dir());
 }
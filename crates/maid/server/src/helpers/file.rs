@@ -3,10 +3,30 @@ use crate::helpers;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use global_placeholders::global;
 use macros_rs::crashln;
-use std::{fs::write, fs::File, path::PathBuf};
+use std::{fs::write, fs::File, path::Component, path::Path, path::PathBuf};
 use tar::{Archive, Builder};
 use uuid::Uuid;
 
+/// rejects paths that would leave the directory they are resolved against
+pub fn relative(path: &str) -> Result<&Path, anyhow::Error> {
+    let path = Path::new(path);
+
+    match path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+        true => Ok(path),
+        false => Err(anyhow::anyhow!("path '{}' must stay inside the workspace", path.display())),
+    }
+}
+
+/// a directory name for a project, safe to join below one of the server's stores
+pub fn project_dir(project: &str) -> String {
+    let name: String = project.chars().map(|char| if char.is_ascii_alphanumeric() || matches!(char, '-' | '_' | '.') { char } else { '_' }).collect();
+
+    match name.trim_start_matches('.') {
+        "" => String::from("_"),
+        name => name.to_string(),
+    }
+}
+
 fn append_to_tar(builder: &mut Builder<GzEncoder<File>>, path: &String) -> Result<(), std::io::Error> {
     let pathbuf = PathBuf::from(path);
 
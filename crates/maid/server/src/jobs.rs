@@ -0,0 +1,147 @@
+use crate::auth::Token;
+
+use chrono::Utc;
+use macros_rs::then;
+use maid_protocol::jobs::{Job, JobStatus};
+use rocket::{delete, get, http::Status, serde::json::Json, State};
+use std::{collections::HashMap, fs::OpenOptions, io::Write, path::PathBuf, sync::Mutex};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// job records and logs kept as files below `<data_dir>/jobs`, plus a cancel handle per running job
+pub struct JobStore {
+    dir: PathBuf,
+    running: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl JobStore {
+    pub fn new(data_dir: &str) -> JobStore {
+        JobStore {
+            dir: PathBuf::from(data_dir).join("jobs"),
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn record_path(&self, id: &str) -> PathBuf { self.dir.join(format!("{id}.json")) }
+
+    fn log_path(&self, id: &str) -> PathBuf { self.dir.join(format!("{id}.log")) }
+
+    pub fn create(&self, task: &str, image: &str, project: &str, token: &str) -> Job {
+        let job = Job {
+            id: Uuid::new_v4().simple().to_string(),
+            task: task.to_string(),
+            image: image.to_string(),
+            project: project.to_string(),
+            token: token.to_string(),
+            status: JobStatus::Running,
+            started: Utc::now().timestamp(),
+            ended: None,
+            code: None,
+        };
+
+        self.save(&job);
+        self.running.lock().unwrap().insert(job.id.clone(), CancellationToken::new());
+
+        return job;
+    }
+
+    pub fn save(&self, job: &Job) {
+        if let Err(err) = std::fs::create_dir_all(&self.dir).and_then(|_| std::fs::write(self.record_path(&job.id), serde_json::to_string(job).unwrap())) {
+            log::error!("unable to save job {}: {err}", job.id);
+        }
+    }
+
+    pub fn finish(&self, job: &mut Job, status: JobStatus, code: Option<i64>) {
+        job.status = status;
+        job.code = code;
+        job.ended = Some(Utc::now().timestamp());
+
+        self.save(job);
+        self.running.lock().unwrap().remove(&job.id);
+    }
+
+    /// handle that fires once the job is cancelled, through the api or by the client
+    pub fn cancellation(&self, id: &str) -> CancellationToken { self.running.lock().unwrap().entry(id.to_string()).or_default().clone() }
+
+    /// returns false when no build is running for the job, e.g. after a server restart
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().unwrap().get(id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn append(&self, id: &str, line: &str) {
+        let file = OpenOptions::new().create(true).append(true).open(self.log_path(id));
+
+        if let Err(err) = file.and_then(|mut file| file.write_all(line.as_bytes())) {
+            log::error!("unable to write log of job {id}: {err}");
+        }
+    }
+
+    pub fn all(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = std::fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+            .filter_map(|contents| serde_json::from_str::<Job>(&contents).ok())
+            .collect();
+
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.started));
+        return jobs;
+    }
+
+    /// finds a job by its id or an unambiguous id prefix
+    pub fn get(&self, id: &str) -> Option<Job> {
+        let mut matches = self.all().into_iter().filter(|job| job.id.starts_with(id));
+
+        match (matches.next(), matches.next()) {
+            (Some(job), None) => Some(job),
+            _ => None,
+        }
+    }
+
+    pub fn log(&self, id: &str) -> Option<String> { std::fs::read_to_string(self.log_path(id)).ok() }
+}
+
+/// a token only sees the jobs it would be allowed to start
+fn visible(job: &Job, token: &Token) -> bool { token.allows_project(&job.project) && token.allows_image(&job.image) }
+
+/// the job with the given id, jobs outside of the token's scope are reported as not found
+fn find(jobs: &JobStore, id: &str, token: &Token) -> Result<Job, Status> { jobs.get(id).filter(|job| visible(job, token)).ok_or(Status::NotFound) }
+
+#[get("/api/jobs?<limit>")]
+pub async fn list(jobs: &State<JobStore>, limit: Option<usize>, token: Token) -> Json<Vec<Job>> {
+    Json(jobs.all().into_iter().filter(|job| visible(job, &token)).take(limit.unwrap_or(50)).collect())
+}
+
+#[get("/api/jobs/<id>")]
+pub async fn get(jobs: &State<JobStore>, id: &str, token: Token) -> Result<Json<Job>, Status> { find(jobs, id, &token).map(Json) }
+
+#[get("/api/jobs/<id>/log")]
+pub async fn output(jobs: &State<JobStore>, id: &str, token: Token) -> Result<String, Status> {
+    let job = find(jobs, id, &token)?;
+    Ok(jobs.log(&job.id).unwrap_or_default())
+}
+
+/// cancels a running job, answering 202 while the build is being stopped and 200 once a stale job was marked cancelled
+#[delete("/api/jobs/<id>")]
+pub async fn cancel(jobs: &State<JobStore>, id: &str, token: Token) -> Result<Status, Status> {
+    let mut job = find(jobs, id, &token)?;
+    then!(job.status != JobStatus::Running, return Err(Status::Conflict));
+
+    log::info!("cancelling job {} (token={})", job.id, token.name);
+
+    match jobs.cancel(&job.id) {
+        true => Ok(Status::Accepted),
+        false => {
+            jobs.finish(&mut job, JobStatus::Cancelled, None);
+            Ok(Status::Ok)
+        }
+    }
+}
@@ -1,143 +1,163 @@
+mod auth;
+mod blobs;
+mod cache;
+mod config;
 mod docker;
+mod executor;
 mod globals;
 mod helpers;
-mod structs;
-mod table;
+mod jobs;
+mod queue;
+mod upload;
 
+use auth::{Endpoint, Token};
+use blobs::BlobStore;
 use bollard::{Docker, API_DEFAULT_VERSION};
+use clap::{Parser, Subcommand};
+use clap_verbosity_flag::{InfoLevel, Verbosity};
+use config::{Config, Overrides};
 use docker::container;
-use macros_rs::{fmtstr, ternary};
+use executor::{docker::DockerExecutor, local::LocalExecutor, Backend};
+use jobs::JobStore;
+use macros_rs::{crashln, string, ternary};
+use maid_protocol::{jobs::JobStatus, Level, Response};
 use rocket::futures::SinkExt;
-use rocket::{get, http::Status, launch, outcome::Outcome, routes, State};
+use rocket::{get, routes, serde::json::Json, State};
 use rocket_ws::{Channel, Message, WebSocket};
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
 use std::env;
-
-struct DockerState {
-    docker: Result<Docker, anyhow::Error>,
+use upload::UploadStore;
+
+#[derive(Parser)]
+#[command(version, after_help = "Config values are resolved in this order, later ones win: defaults, the config file, MAID_SERVER_* variables, command line flags.")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[arg(global = true, short, long, help = "Path to the server config")]
+    config: Option<String>,
+    #[clap(flatten)]
+    overrides: Overrides,
+    #[clap(flatten)]
+    verbose: Verbosity<InfoLevel>,
 }
 
-#[derive(Serialize, Deserialize)]
-enum Level {
-    None,
-    Fatal,
-    Docker,
-    Debug,
-    Error,
-    Notice,
-    Info,
-    Build,
-    Warning,
-    Success,
+#[derive(Subcommand)]
+enum Commands {
+    /// Start the build server
+    Start,
+    /// Print the resolved server config
+    Config,
+    /// Manage access tokens
+    Token {
+        #[command(subcommand)]
+        action: Tokens,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
-enum Kind {
-    Done,
-    Binary,
-    Message,
+#[derive(Subcommand)]
+enum Tokens {
+    /// Create a new scoped token
+    Create {
+        /// Name of the token
+        name: String,
+        #[arg(long = "endpoint", value_enum, help = "Allowed endpoints, defaults to all")]
+        endpoints: Vec<Endpoint>,
+        #[arg(long = "image", help = "Allowed image patterns, defaults to any")]
+        images: Vec<String>,
+        #[arg(long = "project", help = "Allowed project patterns, defaults to any")]
+        projects: Vec<String>,
+    },
+    /// List all tokens
+    List,
+    /// Revoke a token
+    Revoke {
+        /// Name of the token
+        name: String,
+    },
 }
 
-struct Response {
-    level: Level,
-    kind: Kind,
-    message: Option<String>,
-}
-
-impl Response {
-    fn to_string(&self) -> String {
-        let json_value = serde_json::json!({
-            "kind": &self.kind,
-            "level": &self.level,
-            "message": &self.message,
-            "time": chrono::Utc::now().timestamp_millis(),
-        });
-
-        serde_json::to_string(&json_value).unwrap()
-    }
-}
-
-impl From<Response> for Message {
-    fn from(response: Response) -> Self { Message::text(response.to_string()) }
+struct DockerState {
+    docker: Result<Docker, anyhow::Error>,
 }
 
-#[derive(Debug)]
-struct Token(String);
-
-#[rocket::async_trait]
-impl<'r> rocket::request::FromRequest<'r> for Token {
-    type Error = ();
-
-    async fn from_request(request: &'r rocket::Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
-        let token = "test_token1".to_string();
-        let authorization_header = request.headers().get_one("Authorization");
-
-        if let Some(header_value) = authorization_header {
-            if header_value == fmtstr!("Bearer {token}") {
-                let token = header_value.trim_start_matches("Bearer ").to_owned();
-                return Outcome::Success(Token(token));
-            }
-        }
+#[get("/api/health")]
+async fn health(docker_state: &State<DockerState>, config: &State<Config>, _token: Token) -> Json<maid_protocol::health::Route> {
+    use maid_protocol::health::{Route, Status, Value};
+
+    let (mut version, containers) = match &docker_state.docker {
+        Ok(socket) => match socket.version().await {
+            Ok(info) => (
+                format!("Docker v{} (build {})", info.version.unwrap_or_default(), info.git_commit.unwrap_or_default()),
+                container::list(socket).await.unwrap_or_default(),
+            ),
+            Err(err) => (format!("Docker unavailable ({err})"), vec![]),
+        },
+        Err(err) => (format!("Docker unavailable ({err})"), vec![]),
+    };
 
-        Outcome::Error((Status::Unauthorized, ()))
+    if config.executor == Backend::Local || config.local.enabled {
+        version = format!("{version}, local executor");
     }
-}
-
-#[get("/api/health")]
-async fn health(docker_state: &State<DockerState>, _token: Token) -> Value {
-    let socket = &docker_state.docker.as_ref().unwrap();
-    let info = socket.version().await.unwrap();
-    let containers = container::list(socket).await.unwrap();
 
     let uptime = helpers::format::duration(helpers::os::uptime());
-    let version = format!("Docker v{} (build {})", &info.version.clone().unwrap(), &info.git_commit.clone().unwrap());
 
-    json!({
-        "version": {
-            "data": format!("v{}", env!("CARGO_PKG_VERSION")),
-            "hue": "red"
-        },
-        "platform": {
-            "data": format!("{} ({} {})", helpers::os::release(), env::consts::OS, env::consts::ARCH),
-            "hue": "bright red"
-        },
-        "engine": {
-            "data": version,
-            "hue": "yellow"
+    Json(Route {
+        version: Value::new(format!("v{}", env!("CARGO_PKG_VERSION")), "red"),
+        platform: Value::new(format!("{} ({} {})", helpers::os::release(), env::consts::OS, env::consts::ARCH), "bright red"),
+        engine: Value::new(version, "yellow"),
+        status: Status {
+            uptime: Value::new(uptime, "green"),
+            healthy: Value::new(string!(ternary!(helpers::os::health(), "yes", "no")), "cyan"),
+            containers: Value::new(containers, "bright blue"),
         },
-        "status": {
-            "uptime": {
-                "data": uptime,
-                "hue": "green"
-            },
-            "healthy": {
-                "data": ternary!(helpers::os::health(), "yes", "no"),
-                "hue": "cyan"
-            },
-            "containers": {
-                "data": containers,
-                "hue": "bright blue"
-            }
-        }
     })
 }
 
 #[get("/ws/gateway")]
-fn stream(ws: WebSocket, docker_state: &State<DockerState>, _token: Token) -> Channel {
-    let connect_success = Response {
-        level: Level::Success,
-        kind: Kind::Message,
-        message: Some("client connected".to_string()),
-    };
+fn stream<'a>(ws: WebSocket, docker_state: &'a State<DockerState>, queue: &'a State<queue::Queue>, jobs: &'a State<JobStore>, uploads: &'a State<UploadStore>, blobs: &'a State<BlobStore>, config: &'a State<Config>, token: Token) -> Channel<'a> {
+    let connect_success = Response::message(Level::Success, "client connected");
 
     ws.channel(move |mut stream| {
         Box::pin(async move {
-            stream.send(connect_success.into()).await?;
+            stream.send(Message::text(connect_success)).await?;
+
+            let (parsed, backend) = match executor::run::receive(&mut stream, &token, config).await {
+                Ok(Some(received)) => received,
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    log::warn!("unable to receive task: {err}");
+                    return Ok(());
+                }
+            };
+
+            let _slot = match queue.acquire(&token.name, &mut stream).await {
+                Ok(slot) => slot,
+                Err(err) => {
+                    log::warn!("client left the queue: {err}");
+                    return Ok(());
+                }
+            };
+
+            let project = parsed.maidfile.project.as_ref().and_then(|project| project.name.clone()).unwrap_or_default();
+            let mut job = jobs.create(&parsed.info.name, &parsed.info.remote.image, &project, &token.name);
 
-            match docker::run::exec(stream, &docker_state.docker).await {
-                Ok(_) => log::info!("build finished"),
-                Err(_) => log::error!("failed to build"),
+            let result = match backend {
+                Backend::Docker => executor::run::exec(stream, DockerExecutor::new(&docker_state.docker), &parsed, jobs, uploads, blobs, &job.id).await,
+                Backend::Local => executor::run::exec(stream, LocalExecutor::new(&config.local, &config.temp_dir, &job.id), &parsed, jobs, uploads, blobs, &job.id).await,
+            };
+
+            match result {
+                Ok(Some(code)) => {
+                    log::info!("build finished (job={}, code={code})", job.id);
+                    jobs.finish(&mut job, ternary!(code == 0, JobStatus::Finished, JobStatus::Failed), Some(code));
+                }
+                Ok(None) => {
+                    log::warn!("build cancelled (job={})", job.id);
+                    jobs.finish(&mut job, JobStatus::Cancelled, None);
+                }
+                Err(err) => {
+                    log::error!("failed to build (job={}): {err}", job.id);
+                    jobs.finish(&mut job, JobStatus::Failed, None);
+                }
             };
 
             Ok(())
@@ -145,28 +165,68 @@ fn stream(ws: WebSocket, docker_state: &State<DockerState>, _token: Token) -> Ch
     })
 }
 
-#[launch]
-#[tokio::main]
-async fn rocket() -> _ {
-    let http = true;
+fn docker_socket(config: &Config) -> Result<Docker, anyhow::Error> {
+    let docker = &config.docker;
 
-    std::env::set_var("ROCKET_PORT", "3500");
-    std::env::set_var("RUST_LOG", "rocket");
+    let socket = match (&docker.host, &docker.socket) {
+        (Some(host), _) => Docker::connect_with_http(host, docker.timeout, API_DEFAULT_VERSION)?,
+        (None, Some(socket)) => Docker::connect_with_socket(socket, docker.timeout, API_DEFAULT_VERSION)?,
+        (None, None) => Docker::connect_with_socket_defaults()?,
+    };
 
-    globals::init();
-    pretty_env_logger::init();
+    Ok(socket)
+}
 
-    let socket = async move {
-        let socket = match http {
-            true => Docker::connect_with_http("100.79.107.11:4250", 120, API_DEFAULT_VERSION)?.clone(),
-            false => Docker::connect_with_socket_defaults()?.clone(),
-        };
+async fn start(config: Config) {
+    globals::init(&config);
 
-        Ok::<Docker, anyhow::Error>(socket)
-    };
+    if config.tokens.is_empty() && auth::TokenStore::load(&config).tokens.is_empty() {
+        log::warn!("no tokens configured, every request will be rejected");
+    }
 
-    let docker_socket = tokio::spawn(socket);
-    let docker_socket = docker_socket.await.unwrap();
+    if (config.executor == Backend::Local || config.local.enabled) && !config.local.sandboxed() {
+        crashln!("The local executor requires linux, `local.root` and the `local.uid`/`local.gid` of an unprivileged user, builds are never run directly on the host.");
+    }
+
+    let mut figment = rocket::Config::figment().merge(("address", &config.address)).merge(("port", config.port));
+
+    if let Some(tls) = &config.tls {
+        figment = figment.merge(("tls.certs", &tls.certs)).merge(("tls.key", &tls.key));
+    }
 
-    rocket::build().manage(DockerState { docker: docker_socket }).mount("/", routes![health, stream])
+    let docker_socket = docker_socket(&config);
+    let queue = queue::Queue::new(config.max_builds);
+    let jobs = JobStore::new(&config.data_dir);
+    let uploads = UploadStore::new(&config.data_dir);
+    let blobs = BlobStore::new(&config.data_dir);
+    let server = rocket::custom(figment)
+        .manage(DockerState { docker: docker_socket })
+        .manage(queue)
+        .manage(jobs)
+        .manage(uploads)
+        .manage(blobs)
+        .manage(config)
+        .mount("/", routes![health, stream, cache::get, cache::put, jobs::list, jobs::get, jobs::output, jobs::cancel]);
+
+    if let Err(err) = server.launch().await {
+        crashln!("Unable to start the server.\n{err}");
+    }
+}
+
+#[rocket::main]
+async fn main() {
+    let cli = Cli::parse();
+    let config = Config::load(&cli.config, &cli.overrides);
+
+    pretty_env_logger::formatted_builder().filter_level(cli.verbose.log_level_filter()).init();
+
+    match &cli.command {
+        Some(Commands::Token { action }) => match action {
+            Tokens::Create { name, endpoints, images, projects } => auth::create(&config, name, endpoints, images, projects),
+            Tokens::List => auth::list(&config),
+            Tokens::Revoke { name } => auth::revoke(&config, name),
+        },
+        Some(Commands::Config) => println!("{}", toml::to_string(&Config { tokens: vec![], ..config }).unwrap()),
+        Some(Commands::Start) | None => start(config).await,
+    }
 }
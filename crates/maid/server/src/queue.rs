@@ -0,0 +1,123 @@
+use maid_protocol::{Level, Response};
+use rocket::futures::SinkExt;
+use rocket_ws::{stream::DuplexStream, Message};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+struct Ticket {
+    id: u64,
+    token: String,
+}
+
+#[derive(Default)]
+struct State {
+    next: u64,
+    running: usize,
+    per_token: HashMap<String, usize>,
+    waiting: VecDeque<Ticket>,
+}
+
+impl State {
+    /// waiting tickets in the order they will start, alternating between tokens
+    fn order(&self) -> Vec<u64> {
+        let mut seen: HashMap<&String, usize> = HashMap::new();
+        let mut ranked: Vec<(usize, usize, u64)> = vec![];
+
+        for (index, ticket) in self.waiting.iter().enumerate() {
+            let ahead = seen.entry(&ticket.token).or_insert_with(|| self.per_token.get(&ticket.token).copied().unwrap_or_default());
+            ranked.push((*ahead, index, ticket.id));
+            *ahead += 1;
+        }
+
+        ranked.sort();
+        ranked.into_iter().map(|(_, _, id)| id).collect()
+    }
+
+    fn remove(&mut self, id: u64) { self.waiting.retain(|ticket| ticket.id != id) }
+}
+
+/// limits concurrent builds, handing out free slots fairly between tokens
+pub struct Queue {
+    max: usize,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+/// a running build, frees its slot when dropped
+pub struct Slot<'a> {
+    queue: &'a Queue,
+    token: String,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+
+        state.running -= 1;
+        if let Some(count) = state.per_token.get_mut(&self.token) {
+            *count = count.saturating_sub(1);
+        }
+
+        drop(state);
+        self.queue.notify.notify_waiters();
+    }
+}
+
+impl Queue {
+    pub fn new(max: usize) -> Queue {
+        Queue {
+            max: max.max(1),
+            state: Mutex::new(State::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// waits for a free build slot, streaming the queue position to the client while waiting
+    pub async fn acquire(&self, token: &str, stream: &mut DuplexStream) -> Result<Slot<'_>, anyhow::Error> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next;
+
+            state.next += 1;
+            state.waiting.push_back(Ticket { id, token: token.to_string() });
+            id
+        };
+
+        let mut last: Option<usize> = None;
+
+        loop {
+            let notified = self.notify.notified();
+
+            let position = {
+                let mut state = self.state.lock().unwrap();
+                let position = state.order().iter().position(|item| *item == id).unwrap_or_default();
+
+                if position == 0 && state.running < self.max {
+                    state.remove(id);
+                    state.running += 1;
+                    *state.per_token.entry(token.to_string()).or_default() += 1;
+
+                    log::info!("build slot acquired (token={token}, running={})", state.running);
+                    return Ok(Slot { queue: self, token: token.to_string() });
+                }
+
+                position
+            };
+
+            if last != Some(position) {
+                last = Some(position);
+
+                let queued_message = Response::message(Level::Notice, format!("waiting for a build slot, position {} in queue", position + 1));
+
+                if let Err(err) = stream.send(Message::text(queued_message)).await {
+                    self.state.lock().unwrap().remove(id);
+                    self.notify.notify_waiters();
+                    return Err(err.into());
+                }
+            }
+
+            notified.await;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Error};
+use macros_rs::then;
+use maid_protocol::{is_hash, CHUNK_SIZE};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// partial uploads are dropped once they have not been touched for this long
+const STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// workspace archives being received, kept below `<data_dir>/uploads` so a dropped connection can resume them
+pub struct UploadStore {
+    dir: PathBuf,
+    active: Mutex<HashMap<String, u64>>,
+    generation: AtomicU64,
+}
+
+/// an archive being written chunk by chunk, owned by the newest connection uploading it
+pub struct Upload<'a> {
+    store: &'a UploadStore,
+    id: String,
+    generation: u64,
+    file: File,
+    pub path: PathBuf,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl UploadStore {
+    pub fn new(data_dir: &str) -> UploadStore {
+        UploadStore {
+            dir: PathBuf::from(data_dir).join("uploads"),
+            active: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn prune(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else { return };
+
+        for entry in entries.flatten() {
+            let modified = entry.metadata().and_then(|meta| meta.modified()).unwrap_or(SystemTime::now());
+
+            if modified.elapsed().unwrap_or_default() > STALE_AFTER && std::fs::remove_file(entry.path()).is_ok() {
+                log::info!("removed stale upload {}", entry.path().display());
+            }
+        }
+    }
+
+    /// reopens the partial archive, cut back to the last complete chunk
+    fn open(&self, id: &str, size: u64) -> Result<(File, PathBuf, u64), Error> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        let path = self.dir.join(format!("{id}.part"));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let length = file.metadata()?.len();
+        let offset = match length > size {
+            true => 0,
+            false => length - length % CHUNK_SIZE as u64,
+        };
+
+        file.set_len(offset)?;
+
+        Ok((file, path, offset))
+    }
+
+    /// opens the upload for an archive, keeping every complete chunk a previous connection already sent
+    ///
+    /// a connection that dropped without closing may still hold the archive, the new one takes it over
+    pub fn begin(&self, id: &str, size: u64) -> Result<Upload<'_>, Error> {
+        if !is_hash(id) {
+            return Err(anyhow!("invalid upload id '{id}'"));
+        }
+
+        let mut active = self.active.lock().unwrap();
+        self.prune();
+
+        let (file, path, offset) = self.open(id, size)?;
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+
+        if active.insert(id.to_string(), generation).is_some() {
+            log::warn!("taking over archive {id} from an older connection");
+        }
+
+        match offset {
+            0 => log::info!("receiving archive {id}"),
+            offset => log::info!("resuming archive {id} at {offset} bytes"),
+        }
+
+        Ok(Upload {
+            store: self,
+            id: id.to_string(),
+            generation,
+            file,
+            path,
+            offset,
+            size,
+        })
+    }
+}
+
+impl Upload<'_> {
+    pub fn complete(&self) -> bool { self.offset >= self.size }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        if self.offset + data.len() as u64 > self.size {
+            return Err(anyhow!("archive {} is larger than the announced {} bytes", self.id, self.size));
+        }
+
+        // held while writing, so a connection taking over cannot truncate the file in between
+        let active = self.store.active.lock().unwrap();
+
+        if active.get(&self.id) != Some(&self.generation) {
+            return Err(anyhow!("archive {} is being uploaded by a newer connection", self.id));
+        }
+
+        self.file.write_all(data)?;
+        self.offset += data.len() as u64;
+
+        Ok(())
+    }
+
+    /// removes the received archive once it has been handed to the executor
+    pub fn remove(&self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            log::warn!("unable to remove upload {}: {err}", self.path.display());
+        }
+    }
+}
+
+impl Drop for Upload<'_> {
+    fn drop(&mut self) {
+        let mut active = self.store.active.lock().unwrap();
+        then!(active.get(&self.id) == Some(&self.generation), active.remove(&self.id));
+    }
+}